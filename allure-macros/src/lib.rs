@@ -1,4 +1,5 @@
 use darling::ast::NestedMeta;
+use darling::util::PathList;
 use darling::{Error, FromMeta};
 use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned, ToTokens};
@@ -11,8 +12,131 @@ extern crate proc_macro;
 #[derive(Debug, FromMeta)]
 struct MacroArgs {
     test_name: Option<String>,
-    test_description: String,
+    /// Markdown, defaults to the test function's doc comments.
+    test_description: Option<String>,
+    /// Used if neither `ALLURE_RESULTS_DIR` nor `allure.toml` set the results directory.
     allure_dir: Option<String>,
+    /// Fails the test as `broken` if it runs longer.
+    timeout_ms: Option<u64>,
+    /// Passed on to `tokio::test`.
+    flavor: Option<String>,
+    /// Passed on to `tokio::test`.
+    worker_threads: Option<usize>,
+    /// Passed on to `tokio::test`, requires tokio's `test-util` feature.
+    start_paused: Option<bool>,
+    epic: Option<String>,
+    feature: Option<String>,
+    story: Option<String>,
+    severity: Option<Severity>,
+    owner: Option<String>,
+    /// Replace the suites derived from the module path.
+    parent_suite: Option<String>,
+    suite: Option<String>,
+    sub_suite: Option<String>,
+    #[darling(multiple)]
+    tag: Vec<String>,
+    #[darling(multiple)]
+    issue: Vec<String>,
+    #[darling(multiple)]
+    tms: Vec<String>,
+}
+
+#[derive(Debug, FromMeta)]
+#[darling(rename_all = "lowercase")]
+enum Severity {
+    Blocker,
+    Critical,
+    Normal,
+    Minor,
+    Trivial,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Blocker => "blocker",
+            Severity::Critical => "critical",
+            Severity::Normal => "normal",
+            Severity::Minor => "minor",
+            Severity::Trivial => "trivial",
+        }
+    }
+}
+
+impl MacroArgs {
+    /// The attribute which makes the generated function a test. An existing `#[tokio::test]` is
+    /// taken from `func` and used instead of the runtime options of `allure_test`.
+    fn test_attribute(&self, func: &mut ItemFn) -> Result<TokenStream, Error> {
+        let existing = func
+            .attrs
+            .iter()
+            .position(|attr| {
+                let segments = &attr.path().segments;
+                segments.len() == 2 && segments[0].ident == "tokio" && segments[1].ident == "test"
+            })
+            .map(|index| func.attrs.remove(index));
+        let mut options = vec![];
+        if let Some(flavor) = &self.flavor {
+            options.push(quote! { flavor = #flavor });
+        }
+        if let Some(worker_threads) = &self.worker_threads {
+            options.push(quote! { worker_threads = #worker_threads });
+        }
+        if let Some(start_paused) = &self.start_paused {
+            options.push(quote! { start_paused = #start_paused });
+        }
+        if func.sig.asyncness.is_none() {
+            // Synchronous tests need no runtime
+            if let Some(attr) = existing {
+                return Err(
+                    Error::custom("`tokio::test` requires an `async fn` test").with_span(&attr)
+                );
+            }
+            if !options.is_empty() {
+                return Err(Error::custom("runtime options require an `async fn` test")
+                    .with_span(&func.sig.fn_token));
+            }
+            return Ok(quote!(#[::core::prelude::v1::test]));
+        }
+        match existing {
+            Some(attr) if !options.is_empty() => Err(Error::custom(
+                "pass the runtime options either to `allure_test` or to `tokio::test`",
+            )
+            .with_span(&attr)),
+            Some(attr) => Ok(attr.into_token_stream()),
+            None => Ok(quote!(#[::tokio::test(#(#options),*)])),
+        }
+    }
+
+    /// Labels and links of the test, as calls on the `reporter`.
+    fn metadata(&self) -> TokenStream {
+        let single = [
+            ("epic", self.epic.as_deref()),
+            ("feature", self.feature.as_deref()),
+            ("story", self.story.as_deref()),
+            ("severity", self.severity.as_ref().map(Severity::as_str)),
+            ("owner", self.owner.as_deref()),
+            ("parentSuite", self.parent_suite.as_deref()),
+            ("suite", self.suite.as_deref()),
+            ("subSuite", self.sub_suite.as_deref()),
+        ];
+        let labels = single
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .chain(self.tag.iter().map(|value| ("tag", value.as_str())))
+            .map(|(name, value)| {
+                quote! { reporter.add_label(::allure_report::models::Label::new(#name, #value)); }
+            });
+        let links = self
+            .issue
+            .iter()
+            .map(|value| ("issue", value))
+            .chain(self.tms.iter().map(|value| ("tms", value)))
+            .map(|(r#type, value)| {
+                quote! { reporter.add_link(::allure_report::models::Link::named(#r#type, #value)); }
+            });
+        quote! { #(#labels)* #(#links)* }
+    }
 }
 
 #[proc_macro_attribute]
@@ -27,6 +151,21 @@ pub fn allure_test(
 
     let mut func = syn::parse_macro_input!(input as ItemFn);
 
+    let call_args = match test_call_arguments(&func.sig) {
+        Ok(call_args) => call_args,
+        Err(err) => return err.write_errors().into(),
+    };
+    let sync = func.sig.asyncness.is_none();
+    if sync && args.timeout_ms.is_some() {
+        return Error::custom("`timeout_ms` requires an `async fn` test")
+            .with_span(&func.sig.fn_token)
+            .write_errors()
+            .into();
+    }
+    let test_attribute = match args.test_attribute(&mut func) {
+        Ok(test_attribute) => test_attribute,
+        Err(err) => return err.write_errors().into(),
+    };
     let input_span = func.sig.paren_token.span.span();
     func.sig.output = ReturnType::Default;
     let old_inps = func.sig.inputs.clone();
@@ -34,30 +173,64 @@ pub fn allure_test(
     let sig = func.sig.clone().into_token_stream();
 
     let header = quote!(
-        #[::tokio::test]
+        #test_attribute
         #sig
     );
 
-    let allure_dir = args.allure_dir.unwrap_or("allure-results".to_string());
+    let mut metadata = args.metadata();
+    if let Some(description) = args.test_description.clone().or_else(|| doc_comment(&func)) {
+        metadata.extend(quote! { reporter.set_description(#description); });
+    }
+    let allure_dir = match args.allure_dir {
+        Some(dir) => quote! { ::core::option::Option::Some(#dir) },
+        None => quote! { ::core::option::Option::None },
+    };
     let ts = args
         .test_name
         .unwrap_or(func.sig.ident.to_string())
         .into_token_stream();
-    let desc = args.test_description.into_token_stream();
+    let fn_name = func.sig.ident.to_string();
+    let inner_fn_name = create_inner_func_name(&func);
+    let run = match args.timeout_ms {
+        Some(ms) => quote! {
+            ::allure_report::___private_timeout(
+                ::std::time::Duration::from_millis(#ms),
+                #inner_fn_name(#(#call_args),*),
+            )
+        },
+        None => quote! { #inner_fn_name(#(#call_args),*) },
+    };
 
     let block = func.block.clone().into_token_stream();
     let inputx = quote_spanned!(input_span=> #old_inps);
-    let inner_fn_name = create_inner_func_name(&func);
-    let headerx =
-        quote_spanned!(func.sig.span()=> async fn #inner_fn_name(#inputx) -> anyhow::Result<()>);
+    let asyncness = &func.sig.asyncness;
+    let headerx = quote_spanned!(func.sig.span()=> #asyncness fn #inner_fn_name(#inputx) -> anyhow::Result<()>);
 
+    let run_and_finish = if sync {
+        quote! {
+            let _task_handle = ::std::thread::spawn(move || reporter.task_blocking());
+            let outcome = ::allure_report::___private_catch_unwind_sync(|| {
+                helper.clone().___private_sync_scope(|| #run)
+            });
+            helper.___private_finish_blocking(outcome);
+        }
+    } else {
+        quote! {
+            let _task_handle = ::tokio::task::spawn(reporter.task());
+            let outcome = ::allure_report::___private_catch_unwind(helper.clone().___private_scope(#run)).await;
+            helper.___private_finish(outcome).await;
+        }
+    };
     let outer_body = quote_spanned!(func.block.span()=> {
-        let (reporter, mut helper) = ::allure_report::reporter::Reporter::new(#ts, #desc, module_path!(), #allure_dir);
-        let _task_handle = ::tokio::task::spawn(reporter.task());
-        let res = #inner_fn_name(&mut helper).await;
-        let _ = helper.___private_fetch_result().await.unwrap();
-        helper.___private_write_result().await.unwrap();
-        res.expect("Test failed.");
+        #[allow(unused_mut)]
+        let (mut reporter, mut helper) = ::allure_report::reporter::Reporter::with_config(
+            #ts,
+            concat!(module_path!(), "::", #fn_name),
+            module_path!(),
+            ::allure_report::config::Config::___private_resolve(#allure_dir, ::core::env!("CARGO_MANIFEST_DIR")),
+        );
+        #metadata
+        #run_and_finish
     });
 
     let body = quote_spanned!(func.span()=>
@@ -74,88 +247,317 @@ pub fn allure_test(
     out.into()
 }
 
+/// The arguments to call the test's inner function with, tests may take the `TestHelper` or
+/// nothing.
+fn test_call_arguments(sig: &syn::Signature) -> Result<Vec<TokenStream>, Error> {
+    let mut errors = Error::accumulator();
+    let mut call_args = vec![];
+    for input in sig.inputs.iter() {
+        let is_helper = match input {
+            syn::FnArg::Typed(arg) if call_args.is_empty() => is_test_helper(&arg.ty),
+            _ => Ok(false),
+        };
+        match is_helper {
+            Ok(true) => call_args.push(quote! { &mut helper }),
+            Ok(false) => errors.push(
+                Error::custom("tests only take a single `&mut TestHelper` argument")
+                    .with_span(input),
+            ),
+            Err(err) => errors.push(err),
+        }
+    }
+    errors.finish_with(call_args)
+}
+
+/// The function's `///` doc comments without the leading space of each line.
+fn doc_comment(func: &ItemFn) -> Option<String> {
+    let lines = func
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(s),
+                        ..
+                    }),
+                ..
+            }) => Some(s.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n").trim().to_string())
+    }
+}
+
 #[derive(Debug, FromMeta)]
 struct StepArgs {
     step_description: String,
+    /// Record the step's arguments as parameters using their `Debug` output.
+    #[darling(default)]
+    parameters: bool,
+    /// Arguments which are not recorded as parameters.
+    #[darling(default)]
+    skip: PathList,
+    /// Arguments which are recorded with a placeholder for their value, which is never formatted.
+    #[darling(default)]
+    mask: PathList,
+}
+
+/// What an `allure_step`-like function is reported as.
+enum StepKind {
+    Step,
+    Before,
+    After,
+}
+
+impl StepKind {
+    /// The call on the test helper which starts the step, its `_sync` variant for synchronous
+    /// steps.
+    fn start(&self, name: TokenStream, parameters: TokenStream, sync: bool) -> TokenStream {
+        let suffix = if sync { "_sync" } else { "" };
+        let start_step = quote::format_ident!("___private_start_step{suffix}");
+        let start_fixture = quote::format_ident!("___private_start_fixture{suffix}");
+        match self {
+            StepKind::Step => quote! { #start_step(#name, #parameters) },
+            StepKind::Before => quote! {
+                #start_fixture(::allure_report::models::FixtureKind::Before, #name, #parameters)
+            },
+            StepKind::After => quote! {
+                #start_fixture(::allure_report::models::FixtureKind::After, #name, #parameters)
+            },
+        }
+    }
 }
 
 #[proc_macro_attribute]
 pub fn allure_step(
     args: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    step(args, input, StepKind::Step)
+}
+
+/// A setup fixture, takes the same arguments as `allure_step`. It is reported in the test's
+/// container instead of its steps.
+#[proc_macro_attribute]
+pub fn allure_before(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    step(args, input, StepKind::Before)
+}
+
+/// A teardown fixture, takes the same arguments as `allure_step`. It is reported in the test's
+/// container instead of its steps.
+#[proc_macro_attribute]
+pub fn allure_after(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    step(args, input, StepKind::After)
+}
+
+fn step(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+    kind: StepKind,
 ) -> proc_macro::TokenStream {
     let args: StepArgs = match parse_args(args) {
         Ok(value) => value,
         Err(value) => return value,
     };
 
-    let func = syn::parse_macro_input!(input as ItemFn);
-
-    let mut signature = func.vis.to_token_stream();
-    signature.extend(func.sig.to_token_stream());
+    let mut func = syn::parse_macro_input!(input as ItemFn);
+    let parameters = match create_step_parameters(&func, &args) {
+        Ok(parameters) => parameters,
+        Err(err) => return err.write_errors().into(),
+    };
+    let helper = match test_helper_argument(&func.sig) {
+        Ok(helper) => helper,
+        Err(err) => return err.write_errors().into(),
+    };
     let step_name = func.sig.ident.to_string();
+    let description = args.step_description.into_token_stream();
+    let name = quote! { &format!("{}: {}", #step_name, #description) };
 
-    let arguments_with_types = func.sig.inputs.to_token_stream();
-    match func.sig.output.clone() {
-        ReturnType::Default => {}
-        ReturnType::Type(_, x) => {
-            let r = x.as_ref();
-            // assert that Type is anyhow::Result
-            if let syn::Type::Path(p) = r {
-                if p.path.segments.len() == 1 {
-                    let seg = &p.path.segments[0];
-                    assert_eq!(seg.ident.to_string(), "Result");
-                    panic!("Only Result return types are supported here.");
-                }
-            }
+    if func.sig.asyncness.is_none() {
+        // `async_trait` expands before us and leaves a method returning a boxed future
+        if let Some((output, body)) = async_trait_body(&mut func) {
+            let start = kind.start(name, parameters, false);
+            let block = body.to_token_stream();
+            *body = syn::parse2(instrument_step(
+                helper.as_ref(),
+                start,
+                &output,
+                block,
+                false,
+            ))
+            .expect("the instrumented step is a block expression");
+            return func.into_token_stream().into();
         }
+    }
+    let sync = func.sig.asyncness.is_none();
+    let start = kind.start(name, parameters, sync);
+    let output = match &func.sig.output {
+        ReturnType::Default => syn::parse_quote!(()),
+        ReturnType::Type(_, ty) => ty.as_ref().clone(),
     };
-    let output = func.sig.output.to_token_stream();
-    let block = TokenStream::from_iter(
-        func.block
-            .stmts
-            .clone()
-            .into_iter()
-            .map(|t| t.into_token_stream()),
+    let block = &func.block;
+    let body = instrument_step(
+        helper.as_ref(),
+        start,
+        &output,
+        block.to_token_stream(),
+        sync,
     );
+    func.block = syn::parse_quote_spanned!(block.span()=> { #body });
+    func.into_token_stream().into()
+}
 
-    let (is_struct_impl, fn_inputs) = create_inner_call_arguments(&func);
-
-    let obj = if is_struct_impl {
-        quote! { self. }
+/// Wraps the step's `block` into a block which reports the step. The block is kept in the
+/// function itself so steps also work as trait methods. Synchronous steps report through the
+/// `_sync` variants of the helper's functions and run the block in a closure. Steps without a
+/// helper argument report to the current test, and run without being reported outside of one.
+fn instrument_step(
+    helper: Option<&syn::Ident>,
+    start: TokenStream,
+    output: &syn::Type,
+    block: TokenStream,
+    sync: bool,
+) -> TokenStream {
+    let (finalize, dot_await) = if sync {
+        (quote! { ___private_finalize_step_sync }, TokenStream::new())
     } else {
-        quote! {}
+        (quote! { ___private_finalize_step }, quote! { .await })
     };
-
-    let inner_fn_name = create_inner_func_name(&func);
-
-    let test_fn = quote! { pub async fn #inner_fn_name (#arguments_with_types) #output {
-                                       #block
-                                     }
-    };
-    let invocation =
-        quote! { let res: anyhow::Result<_> = #obj #inner_fn_name (#(#fn_inputs),*).await; };
-    let args = args.step_description.into_token_stream();
-    let body = quote_spanned!(func.block.span()=> {
-        test_helper.___private_start_step(&format!("{}: {}", #step_name, #args)).await?;
-        #invocation
-        match res {
-            Ok(x) => {
-                test_helper.___private_finalize_step(::allure_report::models::Status::Passed).await?;
-                Ok(x)
+    // The current test's helper of steps without a helper argument, `None` outside of a test
+    let current = syn::Ident::new("___allure_helper", proc_macro2::Span::call_site());
+    let (declare, scope) = match helper {
+        Some(helper) => (
+            TokenStream::new(),
+            quote! { ::core::option::Option::Some(#helper.clone()) },
+        ),
+        None => (
+            quote! { let mut #current = ::allure_report::TestHelper::current(); },
+            quote! { #current.clone() },
+        ),
+    };
+    let report = |call: TokenStream| match helper {
+        Some(helper) => quote! { let _ = #helper.#call #dot_await; },
+        None => quote! {
+            if let ::core::option::Option::Some(#current) = #current.as_mut() {
+                let _ = #current.#call #dot_await;
             }
-            Err(err) => {
-                test_helper.___private_finalize_step(::allure_report::models::Status::Failed).await?;
-                Err(anyhow::anyhow!(err.to_string()))
+        },
+    };
+    let passed = report(quote! { #finalize(::allure_report::models::Status::Passed, None) });
+    let failed = report(quote! { #finalize(status, Some(details)) });
+    // The status is picked by the impls the return type offers, see `allure_report::step_error`
+    let finish = quote! {
+        let status = {
+            use ::allure_report::step_error::{
+                AnyhowErrorStatus, DisplayErrorStatus, OtherErrorStatus, PassedStatus,
+                StdErrorStatus, Wrap,
+            };
+            (&&&&&Wrap(&res)).step_status()
+        };
+        match status {
+            ::core::option::Option::Some((status, details)) => {
+                #failed
+            }
+            ::core::option::Option::None => {
+                #passed
             }
         }
-    });
+        res
+    };
+    // Fixes the output of the async block or closure, so `?` and `return` in the step's block
+    // convert to the step's return type
+    let output_hint = if contains_impl(output.to_token_stream()) {
+        TokenStream::new()
+    } else {
+        quote! {
+            if let ::core::option::Option::Some(___allure_ret) = ::core::option::Option::None::<#output> {
+                return ___allure_ret;
+            }
+        }
+    };
+    // Steps started in the block, also on other helpers, are nested in this step
+    let run = if sync {
+        quote! {
+            ::allure_report::___private_catch_unwind_sync(|| {
+                ::allure_report::TestHelper::___private_sync_scope_if(#scope, || {
+                    #output_hint
+                    #block
+                })
+            })
+        }
+    } else {
+        quote! {
+            ::allure_report::___private_catch_unwind(::allure_report::TestHelper::___private_scope_if(
+                #scope,
+                async {
+                    #output_hint
+                    #block
+                },
+            ))
+            .await
+        }
+    };
+    let start = report(start);
+    quote! {
+        {
+            #declare
+            #start
+            #[allow(clippy::let_unit_value)]
+            let res = match #run {
+                Ok(res) => res,
+                Err(panic) => {
+                    let (status, details) = panic.status();
+                    #failed
+                    panic.resume()
+                }
+            };
+            #finish
+        }
+    }
+}
 
-    let mut out = TokenStream::new();
-    out.extend(test_fn);
-    out.extend(signature);
-    out.extend(body);
-    out.into()
+/// The return type and body of a method expanded by `async_trait`, i.e. the initializer of the
+/// `let __ret: T = { ... };` inside `Box::pin(async move { ... })`.
+fn async_trait_body(func: &mut ItemFn) -> Option<(syn::Type, &mut syn::Expr)> {
+    let Some(syn::Stmt::Expr(syn::Expr::Call(call), None)) = func.block.stmts.last_mut() else {
+        return None;
+    };
+    match call.func.as_ref() {
+        syn::Expr::Path(p) if p.path.segments.last().is_some_and(|s| s.ident == "pin") => {}
+        _ => return None,
+    }
+    let Some(syn::Expr::Async(future)) = call.args.first_mut() else {
+        return None;
+    };
+    future.block.stmts.iter_mut().find_map(|stmt| {
+        let syn::Stmt::Local(syn::Local {
+            pat: syn::Pat::Type(pat),
+            init: Some(init),
+            ..
+        }) = stmt
+        else {
+            return None;
+        };
+        match pat.pat.as_ref() {
+            syn::Pat::Ident(ident) if ident.ident == "__ret" => {
+                Some((pat.ty.as_ref().clone(), init.expr.as_mut()))
+            }
+            _ => None,
+        }
+    })
 }
 
 fn create_inner_func_name(func: &ItemFn) -> TokenStream {
@@ -189,22 +591,107 @@ fn parse_args<T: FromMeta>(args: proc_macro::TokenStream) -> Result<T, proc_macr
     Ok(args)
 }
 
-fn create_inner_call_arguments(func: &ItemFn) -> (bool, Vec<TokenStream>) {
-    let mut is_struct_impl = false;
-    let mut fn_inputs = vec![];
+/// Whether `ty` names the `TestHelper`, taken in any way.
+fn names_test_helper(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Reference(r) => names_test_helper(&r.elem),
+        syn::Type::Path(p) => p
+            .path
+            .segments
+            .last()
+            .is_some_and(|s| s.ident == "TestHelper"),
+        _ => false,
+    }
+}
+
+/// Whether `ty` is `&mut TestHelper`, the `TestHelper` taken in another way is an error.
+fn is_test_helper(ty: &syn::Type) -> Result<bool, Error> {
+    match ty {
+        syn::Type::Reference(r)
+            if r.mutability.is_some() && matches!(r.elem.as_ref(), syn::Type::Path(_)) =>
+        {
+            Ok(names_test_helper(&r.elem))
+        }
+        ty if names_test_helper(ty) => {
+            Err(Error::custom("the `TestHelper` must be taken as `&mut TestHelper`").with_span(ty))
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The name of the step's `TestHelper` argument, if it takes one. Steps without it report to
+/// the current test.
+fn test_helper_argument(sig: &syn::Signature) -> Result<Option<syn::Ident>, Error> {
+    for input in sig.inputs.iter() {
+        let syn::FnArg::Typed(arg) = input else {
+            continue;
+        };
+        if !is_test_helper(&arg.ty)? {
+            continue;
+        }
+        return match arg.pat.as_ref() {
+            syn::Pat::Ident(pat_ident) => Ok(Some(pat_ident.ident.clone())),
+            pat => Err(
+                Error::custom("the `TestHelper` argument must be bound to a name").with_span(pat),
+            ),
+        };
+    }
+    Ok(None)
+}
+
+/// What masked arguments are recorded as, so that their values never reach the results directory.
+const MASKED_VALUE: &str = "******";
+
+fn create_step_parameters(func: &ItemFn, args: &StepArgs) -> Result<TokenStream, Error> {
+    let mut errors = Error::accumulator();
+    if !args.parameters {
+        for path in args.skip.iter().chain(args.mask.iter()) {
+            errors.push(Error::custom("requires `parameters` to be set").with_span(path));
+        }
+    }
+
+    let mut names = vec![];
+    let mut parameters = vec![];
     for input in func.sig.inputs.iter() {
-        match input {
-            syn::FnArg::Receiver(_) => {
-                // This is a `self` argument
-                is_struct_impl = true;
+        let syn::FnArg::Typed(arg) = input else {
+            continue;
+        };
+        let syn::Pat::Ident(pat_ident) = arg.pat.as_ref() else {
+            continue;
+        };
+        let ident = &pat_ident.ident;
+        names.push(ident);
+        if !args.parameters
+            || names_test_helper(&arg.ty)
+            || args.skip.iter().any(|p| p.is_ident(ident))
+        {
+            continue;
+        }
+        let name = ident.to_string();
+        let parameter = if args.mask.iter().any(|p| p.is_ident(ident)) {
+            quote! {
+                ::allure_report::models::Parameter::new(#name, #MASKED_VALUE).masked()
             }
-            syn::FnArg::Typed(arg) => {
-                if let syn::Pat::Ident(pat_ident) = &arg.pat.as_ref() {
-                    // This is a regular argument, and `pat_ident.ident` is its name
-                    fn_inputs.push(pat_ident.ident.to_token_stream());
-                }
+        } else {
+            quote! {
+                ::allure_report::models::Parameter::new(#name, format!("{:?}", #ident))
             }
+        };
+        parameters.push(parameter);
+    }
+    for path in args.skip.iter().chain(args.mask.iter()) {
+        if !names.iter().any(|name| path.is_ident(*name)) {
+            errors.push(Error::custom("not an argument of this step").with_span(path));
         }
     }
-    (is_struct_impl, fn_inputs)
+    errors.finish()?;
+    Ok(quote! { vec![#(#parameters),*] })
+}
+
+fn contains_impl(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => ident == "impl",
+        proc_macro2::TokenTree::Group(group) => contains_impl(group.stream()),
+        _ => false,
+    })
 }
@@ -3,12 +3,51 @@ use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
-#[derive(Deserialize, Serialize, Debug, Copy, Clone)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Status {
     Passed,
     Failed,
+    Broken,
+    Skipped,
     Pending,
+    Unknown,
+}
+
+impl Status {
+    /// Returns the more severe of both statuses, a test is as bad as its worst step.
+    pub fn worst(self, other: Status) -> Status {
+        if other.severity() > self.severity() {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn severity(self) -> u8 {
+        match self {
+            Status::Passed => 0,
+            Status::Skipped => 1,
+            Status::Pending => 2,
+            Status::Unknown => 3,
+            Status::Broken => 4,
+            Status::Failed => 5,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct StatusDetails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<String>,
+    #[serde(default)]
+    pub known: bool,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub flaky: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -19,11 +58,67 @@ pub struct Attachment {
     pub r#type: String,
 }
 
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterMode {
+    Default,
+    /// The value is shown as `******` in the report.
+    Masked,
+    /// The parameter is not shown in the report at all.
+    Hidden,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub name: String,
+    pub value: String,
+    /// Excluded parameters do not contribute to the history id.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub excluded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ParameterMode>,
+}
+
+impl Parameter {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            excluded: false,
+            mode: None,
+        }
+    }
+
+    pub fn excluded(mut self) -> Self {
+        self.excluded = true;
+        self
+    }
+
+    /// Shows the value as `******` in the report. The value is still written to the result
+    /// file, so secrets should not be passed as the value of a masked parameter.
+    pub fn masked(mut self) -> Self {
+        self.mode = Some(ParameterMode::Masked);
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.mode = Some(ParameterMode::Hidden);
+        self
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Step {
     pub name: String,
     pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_details: Option<StatusDetails>,
     pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<Parameter>,
     pub start: u128,
     pub stop: u128,
 }
@@ -32,37 +127,234 @@ pub struct Step {
 pub struct StepBuilder {
     pub name: String,
     pub attachments: Vec<Attachment>,
+    pub steps: Vec<Step>,
+    pub parameters: Vec<Parameter>,
     pub start: u128,
 }
 
 impl StepBuilder {
-    pub fn into_step(self, status: Status) -> Step {
+    pub fn into_step(self, status: Status, status_details: Option<StatusDetails>) -> Step {
         let StepBuilder {
             name,
             attachments,
+            steps,
+            parameters,
             start,
         } = self;
         Step {
             name,
             status,
+            status_details,
             attachments,
+            steps,
+            parameters,
             start,
             stop: get_epoch_ms(),
         }
     }
 }
 
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FixtureKind {
+    Before,
+    After,
+}
+
+/// A setup or teardown of a test, reported in the test's [`TestResultContainer`].
 #[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureResult {
+    pub name: String,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_details: Option<StatusDetails>,
+    pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<Parameter>,
+    pub start: u128,
+    pub stop: u128,
+}
+
+impl From<Step> for FixtureResult {
+    fn from(step: Step) -> Self {
+        let Step {
+            name,
+            status,
+            status_details,
+            attachments,
+            steps,
+            parameters,
+            start,
+            stop,
+        } = step;
+        FixtureResult {
+            name,
+            status,
+            status_details,
+            attachments,
+            steps,
+            parameters,
+            start,
+            stop,
+        }
+    }
+}
+
+/// Groups test results with the fixtures which ran before and after them.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TestResultContainer {
+    pub uuid: Uuid,
+    pub name: String,
+    /// The uuids of the contained test results.
+    pub children: Vec<Uuid>,
+    pub befores: Vec<FixtureResult>,
+    pub afters: Vec<FixtureResult>,
+    pub start: u128,
+    pub stop: u128,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Label {
     pub name: String,
     pub value: String,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl Label {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Link {
+    /// `issue`, `tms` or `link`
     pub r#type: String,
     pub name: String,
-    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl Link {
+    pub fn new(r#type: impl Into<String>, name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            r#type: r#type.into(),
+            name: name.into(),
+            url: Some(url.into()),
+        }
+    }
+
+    /// A link given only a name, such as an issue key, whose url comes from a link pattern.
+    pub fn named(r#type: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            r#type: r#type.into(),
+            name: name.into(),
+            url: None,
+        }
+    }
+}
+
+/// Groups failures in the report, `categories.json` in the results directory. A result belongs
+/// to a category if the regexes match its whole message and trace and its status is matched.
+/// Reads both the field names of `categories.json` and their snake case.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all(serialize = "camelCase"), deny_unknown_fields)]
+pub struct Category {
+    pub name: String,
+    #[serde(
+        default,
+        alias = "messageRegex",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub message_regex: Option<String>,
+    #[serde(default, alias = "traceRegex", skip_serializing_if = "Option::is_none")]
+    pub trace_regex: Option<String>,
+    /// Any status if empty.
+    #[serde(
+        default,
+        alias = "matchedStatuses",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub matched_statuses: Vec<Status>,
+    /// Marks the results as flaky.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flaky: bool,
+}
+
+impl Category {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            message_regex: None,
+            trace_regex: None,
+            matched_statuses: vec![],
+            flaky: false,
+        }
+    }
+
+    pub fn message_regex(mut self, regex: impl Into<String>) -> Self {
+        self.message_regex = Some(regex.into());
+        self
+    }
+
+    pub fn trace_regex(mut self, regex: impl Into<String>) -> Self {
+        self.trace_regex = Some(regex.into());
+        self
+    }
+
+    pub fn statuses(mut self, statuses: impl IntoIterator<Item = Status>) -> Self {
+        self.matched_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    pub fn flaky(mut self) -> Self {
+        self.flaky = true;
+        self
+    }
+}
+
+/// The build which ran the tests, `executor.json` in the results directory. Allure shows it on the
+/// overview and links the report to the build.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, rename_all(serialize = "camelCase"), deny_unknown_fields)]
+pub struct Executor {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Selects the icon, e.g. `github`, `gitlab`, `jenkins` or `local`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_order: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_url: Option<String>,
+}
+
+impl Executor {
+    /// Fields set in `other` replace the fields of `self`.
+    pub fn merge(self, other: Executor) -> Executor {
+        Executor {
+            name: other.name.or(self.name),
+            r#type: other.r#type.or(self.r#type),
+            url: other.url.or(self.url),
+            build_order: other.build_order.or(self.build_order),
+            build_name: other.build_name.or(self.build_name),
+            build_url: other.build_url.or(self.build_url),
+            report_name: other.report_name.or(self.report_name),
+            report_url: other.report_url.or(self.report_url),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -75,45 +367,85 @@ pub struct TestResult {
     pub test_case_id: Uuid,
     pub full_name: String,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_html: Option<String>,
     pub links: Vec<Link>,
     pub labels: Vec<Label>,
     pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_details: Option<StatusDetails>,
     pub start: u128,
     pub stop: u128,
     pub steps: Vec<Step>,
     pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<Parameter>,
 }
 
 impl TestResult {
     pub fn new(full_name: String, name: String) -> Self {
         Self {
             uuid: Uuid::now_v7(),
-            history_id: Uuid::now_v7(),
-            test_case_id: Uuid::now_v7(),
+            history_id: history_id(&full_name, []),
+            test_case_id: test_case_id(&full_name),
             full_name,
             name,
+            description: None,
+            description_html: None,
             links: vec![],
             labels: vec![],
             status: Status::Pending,
+            status_details: None,
             start: get_epoch_ms(),
             stop: 0,
             steps: vec![],
             attachments: vec![],
+            parameters: vec![],
         }
     }
 }
 
+/// Identifies an open step of a test, so steps running concurrently can be started and
+/// finalized independently.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct StepId(pub u64);
+
+/// A step or fixture which has been started but not finalized yet.
+#[derive(Debug)]
+pub struct OpenStep {
+    pub id: StepId,
+    /// The step it was started in, `None` for steps of the test and fixtures.
+    pub parent: Option<StepId>,
+    pub fixture: Option<FixtureKind>,
+    pub step: StepBuilder,
+    /// The ids of the finalized child steps in `step.steps`.
+    pub child_ids: Vec<StepId>,
+}
+
 #[derive(Debug)]
 pub struct TestResultBuilder {
     pub uuid: Uuid,
     pub full_name: String,
     pub name: String,
+    pub description: Option<String>,
+    pub description_html: Option<String>,
     pub links: Vec<Link>,
     pub labels: Vec<Label>,
     pub start: u128,
-    pub current_step: Option<StepBuilder>,
+    /// Steps and fixtures which have been started but not finalized yet, in the order they were
+    /// started.
+    pub open_steps: Vec<OpenStep>,
     pub steps: Vec<Step>,
+    /// The ids of the finalized steps in `steps`.
+    pub step_ids: Vec<StepId>,
+    pub befores: Vec<FixtureResult>,
+    pub afters: Vec<FixtureResult>,
     pub attachments: Vec<Attachment>,
+    pub parameters: Vec<Parameter>,
+    pub status: Option<Status>,
+    pub status_details: Option<StatusDetails>,
 }
 
 impl TestResultBuilder {
@@ -122,81 +454,247 @@ impl TestResultBuilder {
             uuid: Uuid::now_v7(),
             full_name: full_name.into(),
             name: name.into(),
+            description: None,
+            description_html: None,
             links: vec![],
             labels: vec![Label {
                 name: "suite".to_string(),
                 value: suite.to_string(),
             }],
             start: get_epoch_ms(),
-            current_step: None,
+            open_steps: vec![],
             steps: vec![],
+            step_ids: vec![],
+            befores: vec![],
+            afters: vec![],
             attachments: vec![],
+            parameters: vec![],
+            status: None,
+            status_details: None,
         }
     }
 
-    pub fn start_step(&mut self, name: &str) -> anyhow::Result<&mut StepBuilder> {
-        if self.current_step.is_some() {
-            return Err(anyhow::anyhow!("Current step is not finalized."));
-        }
-        self.current_step = Some(StepBuilder {
-            name: name.into(),
-            attachments: vec![],
-            start: get_epoch_ms(),
+    /// Starts a step, it becomes a child of `parent` if that step is still open when the new one
+    /// is finalized.
+    pub fn start_step(
+        &mut self,
+        id: StepId,
+        parent: Option<StepId>,
+        name: &str,
+    ) -> &mut StepBuilder {
+        self.open(id, parent, None, name)
+    }
+
+    /// Starts a fixture, steps started in it are reported in the test's container.
+    pub fn start_fixture(&mut self, id: StepId, kind: FixtureKind, name: &str) -> &mut StepBuilder {
+        self.open(id, None, Some(kind), name)
+    }
+
+    fn open(
+        &mut self,
+        id: StepId,
+        parent: Option<StepId>,
+        fixture: Option<FixtureKind>,
+        name: &str,
+    ) -> &mut StepBuilder {
+        self.open_steps.push(OpenStep {
+            id,
+            parent,
+            fixture,
+            step: StepBuilder {
+                name: name.into(),
+                attachments: vec![],
+                steps: vec![],
+                parameters: vec![],
+                start: get_epoch_ms(),
+            },
+            child_ids: vec![],
         });
-        return Ok(self.current_step.as_mut().unwrap());
+        &mut self.open_steps.last_mut().unwrap().step
     }
 
-    pub fn current_step(&mut self) -> Option<&mut StepBuilder> {
-        self.current_step.as_mut()
+    pub fn step_mut(&mut self, id: StepId) -> Option<&mut StepBuilder> {
+        self.open_steps
+            .iter_mut()
+            .find(|open| open.id == id)
+            .map(|open| &mut open.step)
     }
 
-    pub fn finalize_step(&mut self, status: Status) {
-        if let Some(step) = self.current_step.take() {
-            self.steps.push(step.into_step(status))
+    /// Finalizes an open step or fixture. Steps are added to their parent, or to the test if the
+    /// parent has been finalized already, ordered by their start and then their id.
+    pub fn finalize_step(
+        &mut self,
+        id: StepId,
+        status: Status,
+        status_details: Option<StatusDetails>,
+    ) {
+        let Some(index) = self.open_steps.iter().position(|open| open.id == id) else {
+            return;
+        };
+        let open = self.open_steps.remove(index);
+        let step = open.step.into_step(status, status_details);
+        match open.fixture {
+            Some(FixtureKind::Before) => self.befores.push(step.into()),
+            Some(FixtureKind::After) => self.afters.push(step.into()),
+            None => {
+                let parent = open
+                    .parent
+                    .and_then(|parent| self.open_steps.iter().position(|o| o.id == parent));
+                let (siblings, ids) = match parent {
+                    Some(parent) => {
+                        let parent = &mut self.open_steps[parent];
+                        (&mut parent.step.steps, &mut parent.child_ids)
+                    }
+                    None => (&mut self.steps, &mut self.step_ids),
+                };
+                let at = siblings
+                    .iter()
+                    .zip(ids.iter())
+                    .take_while(|(s, sid)| (s.start, sid.0) <= (step.start, id.0))
+                    .count();
+                siblings.insert(at, step);
+                ids.insert(at, id);
+            }
+        }
+    }
+
+    /// Finalizes all open steps and fixtures, e.g. after the test panicked. Only the innermost
+    /// ones, which have no open children, receive the details.
+    pub fn finalize_open_steps(&mut self, status: Status, status_details: Option<StatusDetails>) {
+        let innermost = self
+            .open_steps
+            .iter()
+            .filter(|open| !self.open_steps.iter().any(|o| o.parent == Some(open.id)))
+            .map(|open| open.id)
+            .collect::<Vec<_>>();
+        while let Some(open) = self.open_steps.last() {
+            let id = open.id;
+            let details = status_details.clone().filter(|_| innermost.contains(&id));
+            self.finalize_step(id, status, details);
+        }
+    }
+
+    /// Sets the outcome of the test itself, the built result still reports the worst status of
+    /// this and all steps.
+    pub fn set_status(&mut self, status: Status, status_details: Option<StatusDetails>) {
+        self.status = Some(status);
+        self.status_details = status_details;
+    }
+
+    /// Adds the attachment to the step `step` if it is open, otherwise to the test.
+    pub fn add_attachment(&mut self, step: Option<StepId>, attachment: Attachment) {
+        match step.and_then(|step| self.step_mut(step)) {
+            Some(step) => step.attachments.push(attachment),
+            None => self.attachments.push(attachment),
         }
     }
 
-    pub fn add_attachment(&mut self, attachment: Attachment) {
-        self.attachments.push(attachment)
+    pub fn add_label(&mut self, label: Label) {
+        self.labels.push(label)
+    }
+
+    pub fn add_link(&mut self, link: Link) {
+        self.links.push(link)
+    }
+
+    pub fn add_parameter(&mut self, parameter: Parameter) {
+        self.parameters.push(parameter)
     }
 
     pub fn build(self) -> TestResult {
+        self.build_with_container().0
+    }
+
+    /// Builds the result and, if any fixtures ran, the container holding them.
+    pub fn build_with_container(self) -> (TestResult, Option<TestResultContainer>) {
         let Self {
             uuid,
             full_name,
             name,
+            description,
+            description_html,
             links,
             labels,
             start,
-            current_step: _,
+            open_steps: _,
+            step_ids: _,
             steps,
+            befores,
+            afters,
             attachments,
+            parameters,
+            status,
+            status_details,
         } = self;
-        TestResult {
+        let status = steps
+            .iter()
+            .map(|s| s.status)
+            .fold(status.unwrap_or(Status::Passed), Status::worst);
+        let stop = get_epoch_ms();
+        let container = if befores.is_empty() && afters.is_empty() {
+            None
+        } else {
+            Some(TestResultContainer {
+                uuid: Uuid::now_v7(),
+                name: name.clone(),
+                children: vec![uuid],
+                start,
+                stop,
+                befores,
+                afters,
+            })
+        };
+        let result = TestResult {
             uuid,
-            history_id: Uuid::now_v7(),
-            test_case_id: Uuid::now_v7(),
+            history_id: history_id(
+                &full_name,
+                parameters
+                    .iter()
+                    .filter(|p| !p.excluded)
+                    .map(|p| (p.name.as_str(), p.value.as_str())),
+            ),
+            test_case_id: test_case_id(&full_name),
             full_name,
             name,
+            description,
+            description_html,
             links,
             labels,
-            status: if steps
-                .iter()
-                .map(|s| s.status)
-                .any(|s| matches!(s, Status::Failed))
-            {
-                Status::Failed
-            } else {
-                Status::Passed
-            },
+            status,
+            status_details,
             start,
-            stop: get_epoch_ms(),
+            stop,
             steps,
             attachments,
-        }
+            parameters,
+        };
+        (result, container)
     }
 }
 
+/// Identifies a test across runs, Allure uses it to group results of the same test.
+pub fn test_case_id(full_name: &str) -> Uuid {
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, full_name.as_bytes())
+}
+
+/// Identifies a test invocation with a specific set of parameters across runs, Allure uses it
+/// for the history, retries and trend widgets. The order of `parameters` does not matter.
+pub fn history_id<'a>(
+    full_name: &str,
+    parameters: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Uuid {
+    let mut parameters = parameters.into_iter().collect::<Vec<_>>();
+    parameters.sort();
+    let mut key = full_name.to_string();
+    for (name, value) in parameters {
+        key.push('\0');
+        key.push_str(name);
+        key.push('=');
+        key.push_str(value);
+    }
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, key.as_bytes())
+}
+
 fn get_epoch_ms() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -213,7 +711,251 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::TestResult;
+    use super::{
+        history_id, test_case_id, FixtureKind, Parameter, Status, StatusDetails, StepId,
+        TestResult, TestResultBuilder,
+    };
+
+    #[test]
+    fn test_ids_are_stable() {
+        let first = TestResultBuilder::new("name", "crate::module::name", "suite").build();
+        let second = TestResultBuilder::new("name", "crate::module::name", "suite").build();
+        assert_ne!(first.uuid, second.uuid);
+        assert_eq!(first.test_case_id, second.test_case_id);
+        assert_eq!(first.history_id, second.history_id);
+
+        let other = TestResult::new("crate::module::other".into(), "other".into());
+        assert_ne!(first.test_case_id, other.test_case_id);
+        assert_ne!(first.history_id, other.history_id);
+    }
+
+    #[test]
+    fn test_history_id_depends_on_parameters() {
+        let full_name = "crate::module::name";
+        assert_eq!(test_case_id(full_name), test_case_id(full_name));
+        assert_ne!(
+            history_id(full_name, [("a", "1")]),
+            history_id(full_name, [("a", "2")])
+        );
+        assert_eq!(
+            history_id(full_name, [("a", "1"), ("b", "2")]),
+            history_id(full_name, [("b", "2"), ("a", "1")])
+        );
+        assert_ne!(
+            history_id(full_name, []),
+            history_id(full_name, [("a", "1")])
+        );
+    }
+
+    #[test]
+    fn test_ids_serialize_simple() {
+        let result = TestResult::new("crate::module::name".into(), "name".into());
+        let val = serde_json::to_value(&result).unwrap();
+        assert_eq!(
+            val["testCaseId"].as_str().unwrap(),
+            test_case_id("crate::module::name").as_simple().to_string()
+        );
+        assert_eq!(val["historyId"].as_str().unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_parameters() {
+        let build = |parameters: Vec<Parameter>| {
+            let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+            for parameter in parameters {
+                builder.add_parameter(parameter);
+            }
+            builder.build()
+        };
+        let plain = build(vec![]);
+        let with_user = build(vec![Parameter::new("user", "alice")]);
+        let with_excluded = build(vec![
+            Parameter::new("user", "alice"),
+            Parameter::new("request_id", "42").excluded(),
+        ]);
+        assert_ne!(plain.history_id, with_user.history_id);
+        assert_eq!(with_user.history_id, with_excluded.history_id);
+        assert_eq!(plain.test_case_id, with_excluded.test_case_id);
+
+        let val = serde_json::to_value(&with_excluded.parameters).unwrap();
+        assert_eq!(
+            val,
+            serde_json::json!([
+                {"name": "user", "value": "alice"},
+                {"name": "request_id", "value": "42", "excluded": true}
+            ])
+        );
+        let val = serde_json::to_value(Parameter::new("password", "hunter2").masked()).unwrap();
+        assert_eq!(
+            val,
+            serde_json::json!({"name": "password", "value": "hunter2", "mode": "masked"})
+        );
+    }
+
+    #[test]
+    fn test_status_is_worst_of_test_and_steps() {
+        let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        builder.start_step(StepId(0), None, "passing");
+        builder.finalize_step(StepId(0), Status::Passed, None);
+        assert_eq!(builder.build().status, Status::Passed);
+
+        let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        builder.start_step(StepId(0), None, "failing");
+        builder.finalize_step(StepId(0), Status::Failed, None);
+        builder.set_status(Status::Broken, None);
+        assert_eq!(builder.build().status, Status::Failed);
+
+        let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        builder.start_step(StepId(0), None, "passing");
+        builder.finalize_step(StepId(0), Status::Passed, None);
+        builder.set_status(Status::Skipped, None);
+        assert_eq!(builder.build().status, Status::Skipped);
+    }
+
+    #[test]
+    fn test_nested_steps() {
+        let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        builder.start_step(StepId(0), None, "outer");
+        builder.start_step(StepId(1), Some(StepId(0)), "inner 1");
+        builder.finalize_step(StepId(1), Status::Passed, None);
+        builder.start_step(StepId(2), Some(StepId(0)), "inner 2");
+        builder.start_step(StepId(3), Some(StepId(2)), "innermost");
+        assert_eq!(builder.step_mut(StepId(3)).unwrap().name, "innermost");
+        builder.finalize_step(StepId(3), Status::Failed, None);
+        builder.finalize_step(StepId(2), Status::Failed, None);
+        builder.finalize_step(StepId(0), Status::Failed, None);
+        builder.start_step(StepId(4), None, "second");
+        builder.finalize_step(StepId(4), Status::Passed, None);
+        assert!(builder.open_steps.is_empty());
+
+        let result = builder.build();
+        assert_eq!(result.status, Status::Failed);
+        assert_eq!(result.steps.len(), 2);
+        let outer = &result.steps[0];
+        assert_eq!(outer.name, "outer");
+        assert_eq!(
+            outer
+                .steps
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            ["inner 1", "inner 2"]
+        );
+        assert_eq!(outer.steps[1].steps[0].name, "innermost");
+        assert!(result.steps[1].steps.is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_steps() {
+        let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        builder.start_step(StepId(0), None, "parallel");
+        builder.start_step(StepId(1), Some(StepId(0)), "first");
+        builder.start_step(StepId(2), Some(StepId(0)), "second");
+        builder.start_step(StepId(3), Some(StepId(1)), "first child");
+        builder.start_step(StepId(4), Some(StepId(2)), "second child");
+        builder.step_mut(StepId(1)).unwrap().start -= 1;
+        builder.finalize_step(StepId(4), Status::Passed, None);
+        builder.finalize_step(StepId(2), Status::Passed, None);
+        builder.finalize_step(StepId(3), Status::Passed, None);
+        builder.finalize_step(StepId(1), Status::Failed, None);
+        builder.finalize_step(StepId(0), Status::Failed, None);
+        // Outlives its parent
+        builder.start_step(StepId(5), Some(StepId(0)), "detached");
+        builder.finalize_step(StepId(5), Status::Passed, None);
+
+        let result = builder.build();
+        let parallel = &result.steps[0];
+        assert_eq!(parallel.steps[0].name, "first", "ordered by start");
+        assert_eq!(parallel.steps[0].steps[0].name, "first child");
+        assert_eq!(parallel.steps[1].name, "second");
+        assert_eq!(parallel.steps[1].steps[0].name, "second child");
+        assert_eq!(result.steps[1].name, "detached");
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        assert!(builder.build_with_container().1.is_none());
+
+        let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        builder.start_fixture(StepId(0), FixtureKind::Before, "setup");
+        builder.start_step(StepId(1), Some(StepId(0)), "start server");
+        builder.finalize_step(StepId(1), Status::Passed, None);
+        builder.finalize_step(StepId(0), Status::Passed, None);
+        builder.start_step(StepId(2), None, "test step");
+        builder.finalize_step(StepId(2), Status::Passed, None);
+        builder.start_fixture(StepId(3), FixtureKind::After, "teardown");
+        builder.finalize_step(StepId(3), Status::Broken, None);
+        assert!(builder.open_steps.is_empty());
+
+        let (result, container) = builder.build_with_container();
+        let container = container.unwrap();
+        assert_eq!(result.status, Status::Passed);
+        assert_eq!(result.steps.len(), 1);
+        assert_eq!(container.children, [result.uuid]);
+        assert_eq!(container.befores[0].name, "setup");
+        assert_eq!(container.befores[0].steps[0].name, "start server");
+        assert_eq!(container.afters[0].name, "teardown");
+        assert_eq!(container.afters[0].status, Status::Broken);
+        assert_eq!(
+            (container.start, container.stop),
+            (result.start, result.stop)
+        );
+    }
+
+    #[test]
+    fn test_finalize_open_steps() {
+        let mut builder = TestResultBuilder::new("name", "crate::module::name", "suite");
+        builder.start_step(StepId(0), None, "outer");
+        builder.start_fixture(StepId(1), FixtureKind::After, "teardown");
+        builder.start_step(StepId(2), Some(StepId(1)), "inner");
+        let details = StatusDetails {
+            message: Some("panicked".into()),
+            ..Default::default()
+        };
+        builder.finalize_open_steps(Status::Broken, Some(details.clone()));
+        assert!(builder.open_steps.is_empty());
+
+        let (result, container) = builder.build_with_container();
+        let teardown = &container.unwrap().afters[0];
+        assert_eq!(teardown.status, Status::Broken);
+        assert_eq!(teardown.steps[0].name, "inner");
+        assert_eq!(teardown.steps[0].status_details, Some(details.clone()));
+        assert_eq!(teardown.status_details, None);
+        assert_eq!(result.steps[0].name, "outer");
+        assert_eq!(result.steps[0].status_details, Some(details));
+        assert_eq!(result.status, Status::Broken);
+    }
+
+    #[test]
+    fn test_status_details_roundtrip() {
+        let val = serde_json::json!({
+          "name": "Step 1",
+          "status": "broken",
+          "statusDetails": {
+            "message": "connection refused",
+            "trace": "Caused by: connection refused",
+            "known": false,
+            "muted": false,
+            "flaky": true
+          },
+          "attachments": [],
+          "start": 1682358426014usize,
+          "stop": 1682358426014usize
+        });
+        let parsed: super::Step = serde_json::from_value(val.clone()).unwrap();
+        assert_eq!(parsed.status, Status::Broken);
+        assert_eq!(
+            parsed.status_details,
+            Some(StatusDetails {
+                message: Some("connection refused".into()),
+                trace: Some("Caused by: connection refused".into()),
+                flaky: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(serde_json::to_value(parsed).unwrap(), val);
+    }
 
     #[test]
     fn test_roundtrip() {
@@ -1,9 +1,17 @@
 use crate::reporter::Mime;
 use crate::TestHelper;
-use anyhow::anyhow;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 
+/// Returned by failed assertions, steps and tests failing with it are reported as `failed` while
+/// any other error is reported as `broken`.
+#[derive(Debug, thiserror::Error)]
+#[error("Assertion failed: {description}\n{diff}")]
+pub struct AssertionError {
+    pub description: String,
+    pub diff: String,
+}
+
 pub struct WithoutThing;
 
 pub struct WithThing;
@@ -52,23 +60,56 @@ where
         other_thing: Z,
         description: Option<&str>,
     ) -> anyhow::Result<()> {
+        match self.compare(other_thing, description) {
+            None => Ok(()),
+            Some(err) => {
+                self.helper
+                    .attachment(
+                        &format!("Failed: {}", err.description),
+                        Mime::Txt,
+                        err.diff.as_bytes(),
+                    )
+                    .await?;
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Like [`Asserter::is_equals_to`], for synchronous tests.
+    pub fn is_equals_to_sync(
+        &mut self,
+        other_thing: Z,
+        description: Option<&str>,
+    ) -> anyhow::Result<()> {
+        match self.compare(other_thing, description) {
+            None => Ok(()),
+            Some(err) => {
+                self.helper.attachment_sync(
+                    &format!("Failed: {}", err.description),
+                    Mime::Txt,
+                    err.diff.as_bytes(),
+                )?;
+                Err(err.into())
+            }
+        }
+    }
+
+    /// The error of comparing the thing with `other_thing`, if they are not equal.
+    fn compare(&self, other_thing: Z, description: Option<&str>) -> Option<AssertionError> {
         // We are WithThing so this is safe
         if other_thing.eq(self.thing.as_ref().unwrap()) {
-            Ok(())
+            None
         } else {
             let expected = format!("{:#?}", self.thing.as_ref().unwrap());
             let actual = format!("{:#?}", other_thing);
 
             let diff = similar::TextDiff::from_lines(&expected, &actual);
             let diff = diff.unified_diff().missing_newline_hint(false).to_string();
-            self.helper
-                .attachment(
-                    &format!("Failed: {}", description.unwrap_or("equality comparison.")),
-                    Mime::Txt,
-                    diff.as_bytes(),
-                )
-                .await?;
-            Err(anyhow!(diff))
+            let description = description.unwrap_or("equality comparison.");
+            Some(AssertionError {
+                description: description.to_string(),
+                diff,
+            })
         }
     }
 }
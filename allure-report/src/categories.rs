@@ -0,0 +1,242 @@
+//! `categories.json`, which groups the failures of the report. Each test binary writes it when
+//! its first test starts, with the categories added with [`add_category`], then those of the
+//! config, before the defaults. Categories other test binaries wrote are kept, see
+//! [`MergedFile`].
+use crate::config::Config;
+use crate::merged_file::MergedFile;
+use allure_models::{Category, Status};
+use std::collections::HashSet;
+
+pub const CATEGORIES_FILE: &str = "categories.json";
+
+static CATEGORIES: MergedFile<Vec<Category>> =
+    MergedFile::new(CATEGORIES_FILE, vec![], merge_categories);
+
+/// Adds `category` to `categories.json`, replacing a category of the same name, also one of the
+/// config. Categories added before the first test starts are written with the configured ones.
+/// The file is written in the background, a failure to write it is logged.
+pub fn add_category(category: Category) {
+    CATEGORIES.update(|categories| {
+        categories.retain(|c| c.name != category.name);
+        categories.push(category);
+    });
+}
+
+/// Writes the categories into the config's results directory, if this binary has not yet.
+pub(crate) fn write_once(config: &Config) {
+    CATEGORIES.write_once(config, |categories| {
+        *categories = with_config(std::mem::take(categories), config);
+    });
+}
+
+/// The categories `added` with [`add_category`], then the config's and the defaults, a category
+/// replaces those of the same name which follow it.
+fn with_config(added: Vec<Category>, config: &Config) -> Vec<Category> {
+    let mut all = added;
+    all.extend(config.categories.clone());
+    if config.default_categories.unwrap_or(true) {
+        all.extend(defaults());
+    }
+    let mut names = HashSet::new();
+    all.retain(|c| names.insert(c.name.clone()));
+    all
+}
+
+/// Categories for the errors of [`crate::asserter::Asserter`], `allure_test`'s timeout and the
+/// test client. Allure matches the regexes against the whole message, which may span lines.
+fn defaults() -> Vec<Category> {
+    vec![
+        Category::new("Assertion diff")
+            .message_regex(r"(?s).*Assertion failed: .*")
+            .statuses([Status::Failed]),
+        Category::new("Panicked assertions")
+            .message_regex(r"(?s)assertion.*")
+            .statuses([Status::Failed]),
+        Category::new("Server 5xx")
+            .message_regex(r"(?s).*HTTP status server error \(5\d\d.*")
+            .statuses([Status::Broken, Status::Failed]),
+        Category::new("Client 4xx")
+            .message_regex(r"(?s).*HTTP status client error \(4\d\d.*")
+            .statuses([Status::Broken, Status::Failed]),
+        Category::new("Timeouts")
+            .message_regex(
+                r"(?s).*(Test timed out after|operation timed out|deadline has elapsed).*",
+            )
+            .statuses([Status::Broken, Status::Failed]),
+        Category::new("Connection errors")
+            .message_regex(r"(?s).*error sending request.*")
+            .statuses([Status::Broken]),
+    ]
+}
+
+/// `categories` first, followed by the categories of other names already in the file.
+// A `Merge` of the `Vec`
+#[allow(clippy::ptr_arg)]
+fn merge_categories(
+    categories: &Vec<Category>,
+    existing: Option<Vec<u8>>,
+) -> anyhow::Result<Vec<u8>> {
+    let existing: Vec<Category> = existing
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default();
+    let mut merged = categories.clone();
+    merged.extend(
+        existing
+            .into_iter()
+            .filter(|e| !categories.iter().any(|c| c.name == e.name)),
+    );
+    Ok(serde_json::to_vec_pretty(&merged)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{defaults, with_config, CATEGORIES, CATEGORIES_FILE};
+    use crate::config::Config;
+    use crate::test_support::{start_test, TempDir};
+    use crate::{___private_catch_unwind_sync, ___private_timeout, error_status};
+    use allure_models::{Category, Status};
+    use axum::http::StatusCode;
+    use std::time::Duration;
+
+    #[test]
+    fn test_write_categories() {
+        let temp_dir = TempDir::new("categories");
+        let dir = temp_dir.path();
+
+        let custom = Category::new("Database errors")
+            .message_regex(".*connection pool.*")
+            .statuses([Status::Broken])
+            .flaky();
+        CATEGORIES.write(dir, &vec![custom.clone()], false).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.join(CATEGORIES_FILE)).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "name": "Database errors",
+                "messageRegex": ".*connection pool.*",
+                "matchedStatuses": ["broken"],
+                "flaky": true
+            }])
+        );
+
+        // Another test binary writing the defaults keeps the custom category
+        CATEGORIES.write(dir, &defaults(), false).unwrap();
+        let written: Vec<Category> =
+            serde_json::from_slice(&std::fs::read(dir.join(CATEGORIES_FILE)).unwrap()).unwrap();
+        let mut expected = defaults();
+        expected.push(custom);
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_added_categories_replace_configured_ones() {
+        let config = Config {
+            categories: vec![
+                Category::new("Database errors").statuses([Status::Broken]),
+                Category::new("Timeouts"),
+            ],
+            default_categories: Some(false),
+            ..Config::new("allure-results")
+        };
+        let added = Category::new("Database errors").statuses([Status::Failed]);
+        assert_eq!(
+            with_config(vec![added.clone()], &config),
+            [added, Category::new("Timeouts")]
+        );
+        let names = with_config(vec![], &Config::new("allure-results"))
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), defaults().len());
+    }
+
+    /// The first default category Allure puts a result of `status` failing with `message` in.
+    /// Allure matches the whole message.
+    fn default_category(status: Status, message: &str) -> Option<String> {
+        defaults()
+            .into_iter()
+            .find(|category| {
+                let regex = category.message_regex.as_deref().unwrap();
+                regex::Regex::new(&format!("^(?:{regex})$"))
+                    .unwrap()
+                    .is_match(message)
+                    && category.matched_statuses.contains(&status)
+            })
+            .map(|category| category.name)
+    }
+
+    #[tokio::test]
+    async fn test_default_categories_match_messages() {
+        let allure_dir = TempDir::new("default-categories");
+        let (mut helper, _task) = start_test(Config::new(allure_dir.path()));
+        let category = |err: anyhow::Error| {
+            let (status, details) = error_status(&err.context("in the test"));
+            default_category(status, &details.message.unwrap())
+        };
+
+        let err = helper
+            .asserter()
+            .assert_that(1)
+            .is_equals_to(2, Some("the count"))
+            .await
+            .unwrap_err();
+        assert_eq!(category(err).as_deref(), Some("Assertion diff"));
+
+        let panic = ___private_catch_unwind_sync(|| assert_eq!(1, 2)).unwrap_err();
+        let (status, details) = panic.status();
+        assert_eq!(
+            default_category(status, &details.message.unwrap()).as_deref(),
+            Some("Panicked assertions")
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new()
+            .route(
+                "/500",
+                axum::routing::get(|| async { StatusCode::BAD_GATEWAY }),
+            )
+            .route(
+                "/404",
+                axum::routing::get(|| async { StatusCode::NOT_FOUND }),
+            )
+            .route(
+                "/slow",
+                axum::routing::get(|| tokio::time::sleep(Duration::from_secs(10))),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = helper.client();
+        for (path, expected) in [("500", "Server 5xx"), ("404", "Client 4xx")] {
+            let err = client
+                .get(format!("http://{addr}/{path}"))
+                .send()
+                .await
+                .unwrap()
+                .error_for_status()
+                .unwrap_err();
+            assert_eq!(category(err.into()).as_deref(), Some(expected));
+        }
+        let err = client
+            .get(format!("http://{addr}/slow"))
+            .timeout(Duration::from_millis(10))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(category(err.into()).as_deref(), Some("Timeouts"));
+        let err = ___private_timeout(Duration::from_millis(1), async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(category(err).as_deref(), Some("Timeouts"));
+        let err = tokio::time::timeout(Duration::from_millis(1), std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert_eq!(category(err.into()).as_deref(), Some("Timeouts"));
+
+        let err = client.get("http://127.0.0.1:1/").send().await.unwrap_err();
+        assert_eq!(category(err.into()).as_deref(), Some("Connection errors"));
+    }
+}
@@ -0,0 +1,261 @@
+//! Settings shared by the tests of a run. The results directory is resolved from, in order,
+//! `ALLURE_RESULTS_DIR`, the `results_dir` of the `allure.toml` at the workspace root, the
+//! `allure_dir` of `allure_test` and `allure-results`. Relative paths are resolved against the
+//! workspace root, so all crates of a workspace write to the same directory.
+use allure_models::{Category, Executor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+pub const RESULTS_DIR_VAR: &str = "ALLURE_RESULTS_DIR";
+pub const CONFIG_FILE: &str = "allure.toml";
+const DEFAULT_RESULTS_DIR: &str = "allure-results";
+
+/// The resolved settings, and the format of `allure.toml`:
+///
+/// ```toml
+/// results_dir = "target/allure-results"
+/// fsync = true
+/// orphan_age_secs = 600
+///
+/// [labels]
+/// layer = "api"
+///
+/// [links]
+/// issue = "https://issues.example.com/{}"
+///
+/// [redact]
+/// headers = ["authorization"]
+/// fields = ["password"]
+///
+/// [environment]
+/// database = "postgres 16"
+///
+/// [executor]
+/// name = "Nightly"
+/// build_url = "https://ci.example.com/builds/42"
+///
+/// [[categories]]
+/// name = "Database errors"
+/// message_regex = "(?s).*connection pool.*"
+/// matched_statuses = ["broken"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub results_dir: PathBuf,
+    /// Whether result and attachment files are synced to disk before they are renamed into place.
+    pub fsync: bool,
+    /// Attachments no result references are removed when a test binary starts, once they are
+    /// older than this. Without it attachments are kept, as those of tests still running in other
+    /// test binaries are not referenced until the tests finish.
+    pub orphan_age_secs: Option<u64>,
+    /// Labels added to every test, unless the test sets a label of the same name.
+    pub labels: BTreeMap<String, String>,
+    /// Url patterns by link type, `{}` is replaced by the name of links given without an url.
+    pub links: BTreeMap<String, String>,
+    pub redact: Redact,
+    /// Added to the collected values of `environment.properties`.
+    pub environment: BTreeMap<String, String>,
+    /// Replaces the fields of the detected build in `executor.json`.
+    pub executor: Executor,
+    /// Written to `categories.json` after those added with [`crate::add_category`] and before the
+    /// default categories.
+    pub categories: Vec<Category>,
+    /// Whether the default categories are written, defaults to `true`.
+    pub default_categories: Option<bool>,
+    /// The workspace root relative paths were resolved against, `None` for [`Config::new`].
+    #[serde(skip)]
+    pub(crate) workspace_root: Option<PathBuf>,
+}
+
+/// What the client middleware hides in the requests and responses it attaches.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Redact {
+    /// Header names, compared case-insensitively.
+    pub headers: Vec<String>,
+    /// Names of JSON object fields, at any depth of a body.
+    pub fields: Vec<String>,
+}
+
+pub const REDACTED: &str = "[REDACTED]";
+
+impl Config {
+    /// A config writing to `results_dir`, without reading the environment or `allure.toml`.
+    pub fn new(results_dir: impl Into<PathBuf>) -> Self {
+        Config {
+            results_dir: results_dir.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The config of a test in the crate at `manifest_dir` whose `allure_test` was given
+    /// `allure_dir`. Panics if the workspace's `allure.toml` is invalid.
+    pub fn ___private_resolve(allure_dir: Option<&str>, manifest_dir: &str) -> Config {
+        let (root, file) = workspace_config(Path::new(manifest_dir));
+        let file = match file {
+            Ok(file) => file.clone(),
+            Err(err) => panic!("{err}"),
+        };
+        Self::resolve(
+            root,
+            file,
+            std::env::var_os(RESULTS_DIR_VAR).map(PathBuf::from),
+            allure_dir,
+        )
+    }
+
+    fn resolve(
+        root: &Path,
+        file: Option<Config>,
+        env: Option<PathBuf>,
+        allure_dir: Option<&str>,
+    ) -> Config {
+        let file_dir = file
+            .as_ref()
+            .map(|file| file.results_dir.clone())
+            .filter(|dir| !dir.as_os_str().is_empty());
+        let results_dir = env
+            .or(file_dir)
+            .or(allure_dir.map(PathBuf::from))
+            .unwrap_or_else(|| DEFAULT_RESULTS_DIR.into());
+        Config {
+            results_dir: root.join(results_dir),
+            workspace_root: Some(root.to_path_buf()),
+            ..file.unwrap_or_default()
+        }
+    }
+
+    /// The url of a link of type `r#type` which was given only a name.
+    pub fn link_url(&self, r#type: &str, name: &str) -> Option<String> {
+        self.links
+            .get(r#type)
+            .map(|pattern| pattern.replace("{}", name))
+    }
+}
+
+/// The workspace root and its `allure.toml`, read once per test binary.
+fn workspace_config(manifest_dir: &Path) -> &'static (PathBuf, Result<Option<Config>, String>) {
+    static CONFIG: OnceLock<(PathBuf, Result<Option<Config>, String>)> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let root = workspace_root(manifest_dir);
+        let file = read_config_file(&root.join(CONFIG_FILE));
+        (root, file)
+    })
+}
+
+/// The config at `path`, if there is one. A file which can not be read or is invalid is an
+/// error, the tests would otherwise report to another directory or without the redactions.
+fn read_config_file(path: &Path) -> Result<Option<Config>, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Failed to read {}: {err}", path.display())),
+    };
+    toml::from_str(&content)
+        .map(Some)
+        .map_err(|err| format!("Invalid {}: {err}", path.display()))
+}
+
+/// The nearest directory above `manifest_dir` whose `Cargo.toml` defines a workspace, or
+/// `manifest_dir` if the crate is not part of one.
+fn workspace_root(manifest_dir: &Path) -> PathBuf {
+    manifest_dir
+        .ancestors()
+        .find(|dir| {
+            std::fs::read_to_string(dir.join("Cargo.toml"))
+                .ok()
+                .and_then(|manifest| manifest.parse::<toml::Table>().ok())
+                .is_some_and(|manifest| manifest.contains_key("workspace"))
+        })
+        .unwrap_or(manifest_dir)
+        .to_path_buf()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_config_file, Config};
+    use crate::test_support::TempDir;
+    use allure_models::{Category, Status};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_results_dir_resolution() {
+        let root = Path::new("/workspace");
+        let file = Some(Config::new("from-file"));
+        let resolve = |file: Option<Config>, env: Option<&str>, attribute: Option<&str>| {
+            Config::resolve(root, file, env.map(PathBuf::from), attribute).results_dir
+        };
+        assert_eq!(
+            resolve(file.clone(), Some("from-env"), Some("from-attribute")),
+            Path::new("/workspace/from-env")
+        );
+        assert_eq!(
+            resolve(file, None, Some("from-attribute")),
+            Path::new("/workspace/from-file")
+        );
+        assert_eq!(
+            resolve(Some(Config::default()), None, Some("from-attribute")),
+            Path::new("/workspace/from-attribute")
+        );
+        assert_eq!(
+            resolve(None, None, None),
+            Path::new("/workspace/allure-results")
+        );
+        assert_eq!(
+            resolve(None, Some("/tmp/results"), None),
+            Path::new("/tmp/results")
+        );
+    }
+
+    #[test]
+    fn test_config_file() {
+        let config: Config = toml::from_str(
+            r#"
+            results_dir = "target/allure-results"
+
+            [labels]
+            layer = "api"
+
+            [links]
+            issue = "https://issues.example.com/{}"
+
+            [redact]
+            headers = ["Authorization"]
+
+            [[categories]]
+            name = "Database errors"
+            matched_statuses = ["broken"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.labels["layer"], "api");
+        assert_eq!(
+            config.link_url("issue", "BUG-1").as_deref(),
+            Some("https://issues.example.com/BUG-1")
+        );
+        assert_eq!(config.link_url("tms", "TC-1"), None);
+        assert_eq!(config.redact.headers, ["Authorization"]);
+        assert_eq!(
+            config.categories,
+            [Category::new("Database errors").statuses([Status::Broken])]
+        );
+        assert!(toml::from_str::<Config>("result_dir = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn test_invalid_config_file_is_an_error() {
+        let dir = TempDir::new("invalid-config");
+        let path = dir.path().join(super::CONFIG_FILE);
+        std::fs::write(&path, "result_dir = \"typo\"").unwrap();
+        let err = read_config_file(&path).unwrap_err();
+        assert!(err.starts_with("Invalid "), "{err}");
+        assert!(err.contains("result_dir"), "{err}");
+        std::fs::write(&path, "fsync = true").unwrap();
+        assert!(read_config_file(&path).unwrap().unwrap().fsync);
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_config_file(&path).unwrap().is_none());
+    }
+}
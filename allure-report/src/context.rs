@@ -0,0 +1,120 @@
+//! The test an `allure_test` is running, kept as a task-local so steps and [`attach`] can report
+//! to it without being handed a [`TestHelper`].
+use crate::reporter::Mime;
+use crate::TestHelper;
+use allure_models::StepId;
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+tokio::task_local! {
+    static CURRENT: TestHelper;
+}
+
+impl TestHelper {
+    /// A helper reporting to the test running on this task, if there is one.
+    pub fn current() -> Option<TestHelper> {
+        CURRENT.try_with(TestHelper::clone).ok()
+    }
+
+    /// The innermost step open on this task, requests made through the test's client are
+    /// attached to it.
+    pub(crate) fn current_step_id() -> Option<StepId> {
+        CURRENT
+            .try_with(|helper| helper.current_step())
+            .ok()
+            .flatten()
+    }
+
+    /// Runs `fut` with this helper's test as the current test.
+    pub async fn ___private_scope<F: Future>(self, fut: F) -> F::Output {
+        CURRENT.scope(self, fut).await
+    }
+
+    /// Runs `f` with this helper's test as the current test.
+    pub fn ___private_sync_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        CURRENT.sync_scope(self, f)
+    }
+
+    /// Runs `fut` with `helper`'s test as the current test, or as it is without a helper.
+    pub async fn ___private_scope_if<F: Future>(helper: Option<TestHelper>, fut: F) -> F::Output {
+        match helper {
+            Some(helper) => helper.___private_scope(fut).await,
+            None => fut.await,
+        }
+    }
+
+    /// Runs `f` with `helper`'s test as the current test, or as it is without a helper.
+    pub fn ___private_sync_scope_if<R>(helper: Option<TestHelper>, f: impl FnOnce() -> R) -> R {
+        match helper {
+            Some(helper) => helper.___private_sync_scope(f),
+            None => f(),
+        }
+    }
+}
+
+/// Runs `fut` with the current test, if any, so steps in it report to that test. Futures handed
+/// to another task have to be wrapped, the current test is not propagated by itself.
+pub fn in_current_test<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let helper = TestHelper::current();
+    async move {
+        match helper {
+            Some(helper) => CURRENT.scope(helper, fut).await,
+            None => fut.await,
+        }
+    }
+}
+
+/// Spawns `fut` on the tokio runtime, steps in it report to the current test.
+pub fn spawn<F>(fut: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(in_current_test(fut))
+}
+
+/// Attaches `content` to the innermost open step of the current test, or to the test itself.
+pub async fn attach(name: &str, mime: Mime, content: &[u8]) -> anyhow::Result<()> {
+    let mut helper = TestHelper::current()
+        .ok_or_else(|| anyhow::anyhow!("No allure test is running on this task."))?;
+    helper.attachment(name, mime, content).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::{attach, spawn};
+    use crate::config::Config;
+    use crate::reporter::Mime;
+    use crate::test_support::{start_test, TempDir};
+    use crate::TestHelper;
+
+    #[tokio::test]
+    async fn test_current_test_is_task_local() {
+        assert!(TestHelper::current().is_none());
+        assert!(attach("outside", Mime::Txt, b"").await.is_err());
+
+        let allure_dir = TempDir::new("current-test");
+        let (mut helper, _task) = start_test(Config::new(allure_dir.path()));
+
+        helper
+            .clone()
+            .___private_scope(async {
+                attach("scoped", Mime::Txt, b"").await.unwrap();
+                spawn(async { attach("spawned", Mime::Txt, b"").await.unwrap() })
+                    .await
+                    .unwrap();
+                tokio::spawn(async { assert!(TestHelper::current().is_none()) })
+                    .await
+                    .unwrap();
+            })
+            .await;
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        let names = result
+            .attachments
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["scoped", "spawned"]);
+    }
+}
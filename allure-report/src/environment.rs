@@ -0,0 +1,354 @@
+//! `environment.properties` and `executor.json`, shown on Allure's overview. Each test binary
+//! writes them when its first test starts, into that test's results directory. The properties
+//! are merged with those of other test binaries, see [`MergedFile`].
+use crate::config::Config;
+use crate::helpers::{run_blocking, write_atomic};
+use crate::merged_file::MergedFile;
+use allure_models::Executor;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const ENVIRONMENT_FILE: &str = "environment.properties";
+pub const EXECUTOR_FILE: &str = "executor.json";
+
+static ENVIRONMENT: MergedFile<BTreeMap<String, String>> =
+    MergedFile::new(ENVIRONMENT_FILE, BTreeMap::new(), merge_properties);
+
+/// Adds `key` to `environment.properties`, replacing the value collected or configured for it.
+/// Values set before the first test starts are written with the collected ones. The file is
+/// written in the background, a failure to write it is logged.
+pub fn set_environment(key: impl Into<String>, value: impl Into<String>) {
+    let (key, value) = (key.into(), value.into());
+    ENVIRONMENT.update(|values| {
+        values.insert(key, value);
+    });
+}
+
+/// Writes both files into the config's results directory, if this binary has not yet.
+pub(crate) fn write_once(config: &Config) {
+    let written = ENVIRONMENT.write_once(config, |values| {
+        let root = match &config.workspace_root {
+            Some(root) => root.clone(),
+            None => std::env::current_dir().unwrap_or_default(),
+        };
+        let mut all = collect(&root);
+        all.extend(config.environment.clone());
+        all.append(values);
+        *values = all;
+    });
+    if !written {
+        return;
+    }
+
+    let executor = Executor {
+        name: Some(gethostname::gethostname().to_string_lossy().into_owned()),
+        r#type: Some("local".into()),
+        ..Default::default()
+    }
+    .merge(ci_executor(|name| std::env::var(name).ok()).unwrap_or_default())
+    .merge(config.executor.clone());
+    let (dir, fsync) = (config.results_dir.clone(), config.fsync);
+    run_blocking(move || {
+        let json = serde_json::to_vec_pretty(&executor).expect("Executor serializes");
+        if let Err(err) = write_atomic(&dir.join(EXECUTOR_FILE), &json, fsync) {
+            tracing::warn!("Failed to write {EXECUTOR_FILE}: {err}");
+        }
+    });
+}
+
+/// The toolchain, the crate under test and the checked out git commit.
+fn collect(root: &Path) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+    let mut insert = |key: &str, value: &str| {
+        if !value.is_empty() {
+            values.insert(key.to_string(), value.to_string());
+        }
+    };
+    insert("rust.version", env!("ALLURE_RUSTC_VERSION"));
+    insert("rust.target", env!("ALLURE_TARGET"));
+    insert("cargo.profile", env!("ALLURE_PROFILE"));
+    insert("crate.allure-report", env!("CARGO_PKG_VERSION"));
+    // Set by cargo when running the tests of the crate under test
+    if let (Ok(name), Ok(version)) = (
+        std::env::var("CARGO_PKG_NAME"),
+        std::env::var("CARGO_PKG_VERSION"),
+    ) {
+        insert(&format!("crate.{name}"), &version);
+    }
+    if let Some((commit, branch)) = git_head(root) {
+        insert("git.commit", &commit);
+        insert("git.branch", branch.as_deref().unwrap_or_default());
+    }
+    values
+}
+
+/// The commit and branch checked out in the git repository containing `dir`, read from `.git`.
+fn git_head(dir: &Path) -> Option<(String, Option<String>)> {
+    let dot_git = dir
+        .ancestors()
+        .map(|dir| dir.join(".git"))
+        .find(|dot_git| dot_git.exists())?;
+    // Worktrees and submodules have a `.git` file pointing to the git directory
+    let git_dir = if dot_git.is_file() {
+        let content = std::fs::read_to_string(&dot_git).ok()?;
+        dot_git
+            .parent()?
+            .join(content.strip_prefix("gitdir:")?.trim())
+    } else {
+        dot_git
+    };
+    // Worktrees keep their branches in the main repository
+    let common_dir = std::fs::read_to_string(git_dir.join("commondir"))
+        .map(|common| git_dir.join(common.trim()))
+        .unwrap_or_else(|_| git_dir.clone());
+
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let Some(reference) = head.trim().strip_prefix("ref:").map(str::trim) else {
+        return Some((head.trim().to_string(), None));
+    };
+    let branch = reference.strip_prefix("refs/heads/").map(str::to_string);
+    let commit = [&git_dir, &common_dir]
+        .iter()
+        .find_map(|dir| std::fs::read_to_string(dir.join(reference)).ok())
+        .map(|commit| commit.trim().to_string())
+        .or_else(|| {
+            let packed = std::fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+            packed.lines().find_map(|line| {
+                let (commit, name) = line.split_once(' ')?;
+                (name == reference).then(|| commit.to_string())
+            })
+        })?;
+    Some((commit, branch))
+}
+
+/// The build of the CI service the tests run on, from the variables it sets.
+fn ci_executor(var: impl Fn(&str) -> Option<String>) -> Option<Executor> {
+    if var("GITHUB_ACTIONS").is_some() {
+        let server = var("GITHUB_SERVER_URL").unwrap_or_else(|| "https://github.com".into());
+        let repository = var("GITHUB_REPOSITORY").unwrap_or_default();
+        return Some(Executor {
+            name: Some("GitHub Actions".into()),
+            r#type: Some("github".into()),
+            url: Some(format!("{server}/{repository}/actions")),
+            build_order: var("GITHUB_RUN_NUMBER").and_then(|n| n.parse().ok()),
+            build_name: var("GITHUB_WORKFLOW").map(|workflow| {
+                format!(
+                    "{workflow} #{}",
+                    var("GITHUB_RUN_NUMBER").unwrap_or_default()
+                )
+            }),
+            build_url: var("GITHUB_RUN_ID")
+                .map(|id| format!("{server}/{repository}/actions/runs/{id}")),
+            ..Default::default()
+        });
+    }
+    if var("GITLAB_CI").is_some() {
+        return Some(Executor {
+            name: Some("GitLab".into()),
+            r#type: Some("gitlab".into()),
+            url: var("CI_PROJECT_URL"),
+            build_order: var("CI_PIPELINE_IID").and_then(|n| n.parse().ok()),
+            build_name: var("CI_PIPELINE_IID").map(|iid| format!("Pipeline #{iid}")),
+            build_url: var("CI_PIPELINE_URL"),
+            ..Default::default()
+        });
+    }
+    if let Some(url) = var("JENKINS_URL") {
+        return Some(Executor {
+            name: Some("Jenkins".into()),
+            r#type: Some("jenkins".into()),
+            url: Some(url),
+            build_order: var("BUILD_NUMBER").and_then(|n| n.parse().ok()),
+            build_name: var("BUILD_TAG"),
+            build_url: var("BUILD_URL"),
+            ..Default::default()
+        });
+    }
+    None
+}
+
+/// The `environment.properties` with `values` merged into the existing file, values of other
+/// test binaries are kept.
+fn merge_properties(
+    values: &BTreeMap<String, String>,
+    existing: Option<Vec<u8>>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut merged = existing
+        .map(|content| parse_properties(&String::from_utf8_lossy(&content)))
+        .unwrap_or_default();
+    merged.extend(values.clone());
+    let content = merged
+        .iter()
+        .map(|(key, value)| format!("{}={}\n", escape(key, true), escape(value, false)))
+        .collect::<String>();
+    Ok(content.into_bytes())
+}
+
+/// Escapes as `java.util.Properties` reads it, non-ASCII characters as `\uXXXX`.
+fn escape(s: &str, key: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if key || i == 0 => escaped.push_str("\\ "),
+            c if c.is_ascii() => escaped.push(c),
+            c => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    escaped.push_str(&format!("\\u{unit:04x}"));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// Reads the properties [`merge_properties`] writes, lines are not continued.
+fn parse_properties(content: &str) -> BTreeMap<String, String> {
+    let mut properties = BTreeMap::new();
+    for line in content.lines() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with(['#', '!']) {
+            continue;
+        }
+        let mut escaped = false;
+        let end = line
+            .char_indices()
+            .find(|&(_, c)| {
+                let separator = !escaped && matches!(c, '=' | ':' | ' ' | '\t');
+                escaped = !escaped && c == '\\';
+                separator
+            })
+            .map_or(line.len(), |(i, _)| i);
+        let (key, value) = line.split_at(end);
+        let value = value.trim_start_matches([' ', '\t']);
+        let value = value
+            .strip_prefix(['=', ':'])
+            .unwrap_or(value)
+            .trim_start_matches([' ', '\t']);
+        properties.insert(unescape(key), unescape(value));
+    }
+    properties
+}
+
+fn unescape(s: &str) -> String {
+    let mut units = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('f') => '\u{c}',
+                Some('u') => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    units.push(u16::from_str_radix(&hex, 16).unwrap_or(0xfffd));
+                    continue;
+                }
+                Some(c) => c,
+                None => break,
+            },
+            c => c,
+        };
+        units.extend(c.encode_utf16(&mut [0; 2]).iter());
+    }
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ci_executor, git_head, parse_properties, ENVIRONMENT, ENVIRONMENT_FILE};
+    use crate::test_support::TempDir;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn test_properties_round_trip() {
+        let values = BTreeMap::from([
+            (
+                "rust.version".to_string(),
+                "rustc 1.80.0 (abc 2024)".to_string(),
+            ),
+            (
+                "key with = and :".to_string(),
+                " leading\\space\n#".to_string(),
+            ),
+            ("unicode".to_string(), "größe 🦀".to_string()),
+        ]);
+        let temp_dir = TempDir::new("properties");
+        let dir = temp_dir.path();
+
+        ENVIRONMENT.write(dir, &values, false).unwrap();
+        let other = BTreeMap::from([("crate.other".to_string(), "0.2.0".to_string())]);
+        ENVIRONMENT.write(dir, &other, true).unwrap();
+
+        let content = std::fs::read_to_string(dir.join(ENVIRONMENT_FILE)).unwrap();
+        assert!(content.is_ascii());
+        let mut expected = values;
+        expected.extend(other);
+        assert_eq!(parse_properties(&content), expected);
+        assert_eq!(
+            parse_properties("# comment\n  a = 1\nb:2\nc 3\n"),
+            BTreeMap::from([
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string()),
+                ("c".to_string(), "3".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ci_executor() {
+        let vars = HashMap::from([
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_REPOSITORY", "acme/app"),
+            ("GITHUB_RUN_ID", "123"),
+            ("GITHUB_RUN_NUMBER", "7"),
+            ("GITHUB_WORKFLOW", "CI"),
+        ]);
+        let executor = ci_executor(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(executor.r#type.as_deref(), Some("github"));
+        assert_eq!(executor.build_order, Some(7));
+        assert_eq!(executor.build_name.as_deref(), Some("CI #7"));
+        assert_eq!(
+            executor.build_url.as_deref(),
+            Some("https://github.com/acme/app/actions/runs/123")
+        );
+        assert!(ci_executor(|_| None).is_none());
+    }
+
+    #[test]
+    fn test_git_head() {
+        let temp_dir = TempDir::new("git-head");
+        let dir = temp_dir.path();
+        let git_dir = dir.join(".git");
+        std::fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        std::fs::create_dir_all(dir.join("crate")).unwrap();
+
+        std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::write(
+            git_dir.join("packed-refs"),
+            "# pack-refs\nabc123 refs/heads/main\n",
+        )
+        .unwrap();
+        assert_eq!(
+            git_head(&dir.join("crate")),
+            Some(("abc123".to_string(), Some("main".to_string())))
+        );
+
+        std::fs::write(git_dir.join("refs/heads/main"), "def456\n").unwrap();
+        assert_eq!(
+            git_head(dir),
+            Some(("def456".to_string(), Some("main".to_string())))
+        );
+
+        std::fs::write(git_dir.join("HEAD"), "def456\n").unwrap();
+        assert_eq!(git_head(dir), Some(("def456".to_string(), None)));
+    }
+}
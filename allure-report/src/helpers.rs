@@ -1,15 +1,340 @@
-use std::path::PathBuf;
+use crate::config::Config;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 
-#[tracing::instrument(skip(content))]
+#[tracing::instrument(skip(content, config))]
 pub(crate) async fn write_attachment(
     mime: crate::reporter::Mime,
     content: &[u8],
-    mut allure_dir: PathBuf,
+    config: &Config,
 ) -> anyhow::Result<PathBuf> {
-    let of = format!("{}-attachment.{}", Uuid::now_v7(), mime.as_ext()).into();
-    allure_dir.push(&of);
+    let (of, path) = attachment_path(mime, &config.results_dir);
     tracing::debug!("Writing attachment");
-    tokio::fs::write(allure_dir, &content).await?;
+    write_atomic_async(&path, content, config.fsync).await?;
     Ok(of)
 }
+
+#[tracing::instrument(skip(content, config))]
+pub(crate) fn write_attachment_blocking(
+    mime: crate::reporter::Mime,
+    content: &[u8],
+    config: &Config,
+) -> anyhow::Result<PathBuf> {
+    let (of, path) = attachment_path(mime, &config.results_dir);
+    tracing::debug!("Writing attachment");
+    write_atomic(&path, content, config.fsync)?;
+    Ok(of)
+}
+
+/// A new attachment's file name and its path in `allure_dir`.
+fn attachment_path(mime: crate::reporter::Mime, allure_dir: &Path) -> (PathBuf, PathBuf) {
+    let of: PathBuf = format!("{}-attachment.{}", Uuid::now_v7(), mime.as_ext()).into();
+    let path = allure_dir.join(&of);
+    (of, path)
+}
+
+/// Replaces the file at `path` by renaming a temporary file of the same directory, so neither
+/// Allure nor other test binaries see it partially written, even if the test binary is killed.
+/// With `fsync` the file is on disk before it is renamed, and the rename before this returns.
+pub(crate) fn write_atomic(path: &Path, content: &[u8], fsync: bool) -> std::io::Result<()> {
+    let tmp = temp_path(path);
+    let written = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(content)?;
+        if fsync {
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp, path)
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written?;
+    if fsync {
+        sync_parent(path)?;
+    }
+    Ok(())
+}
+
+/// Like [`write_atomic`], on the tokio runtime.
+pub(crate) async fn write_atomic_async(
+    path: &Path,
+    content: &[u8],
+    fsync: bool,
+) -> std::io::Result<()> {
+    let tmp = temp_path(path);
+    let written = async {
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        file.write_all(content).await?;
+        file.flush().await?;
+        if fsync {
+            file.sync_all().await?;
+        }
+        drop(file);
+        tokio::fs::rename(&tmp, path).await
+    }
+    .await;
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&tmp).await;
+    }
+    written?;
+    if fsync {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || sync_parent(&path)).await??;
+    }
+    Ok(())
+}
+
+/// Hidden and without the suffixes Allure reads, so it is skipped until renamed.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, Uuid::now_v7()))
+}
+
+/// Syncs the directory containing `path`, which makes a rename in it durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+/// Directories cannot be opened for syncing, renames are durable once the file system says so.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Removes, once per test binary, temporary files left by [`write_atomic`] in the config's results
+/// directory, test binaries which were killed leave them behind. With the config's
+/// `orphan_age_secs`, attachments no result or container references are removed once they are
+/// that old. Attachments of tests still running in other test binaries are not referenced yet,
+/// so this is only safe if no test takes that long.
+pub(crate) fn remove_orphans_once(config: &Config) {
+    static REMOVED: Once = Once::new();
+    REMOVED.call_once(|| {
+        let dir = config.results_dir.clone();
+        let attachment_age = config.orphan_age_secs.map(Duration::from_secs);
+        run_blocking(move || {
+            if let Err(err) = remove_orphans(&dir, attachment_age) {
+                tracing::warn!("Failed to remove orphaned files: {err}");
+            }
+        });
+    });
+}
+
+/// Temporary files are renamed right after they are written, older ones were left behind.
+const TEMP_FILE_AGE: Duration = Duration::from_secs(60 * 60);
+
+fn remove_orphans(dir: &Path, attachment_age: Option<Duration>) -> std::io::Result<()> {
+    let mut referenced = HashSet::new();
+    let mut candidates = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if attachment_age.is_some()
+            && (name.ends_with("-result.json") || name.ends_with("-container.json"))
+        {
+            // Files of other tests may be replaced or removed meanwhile
+            let Ok(content) = std::fs::read(entry.path()) else {
+                continue;
+            };
+            if let Ok(json) = serde_json::from_slice::<Value>(&content) {
+                collect_sources(&json, &mut referenced);
+            }
+        } else if name.starts_with('.') && name.ends_with(".tmp") {
+            candidates.push((name, entry.path(), TEMP_FILE_AGE));
+        } else if let (true, Some(age)) = (name.contains("-attachment."), attachment_age) {
+            candidates.push((name, entry.path(), age));
+        }
+    }
+    for (name, path, min_age) in candidates {
+        let old = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= min_age);
+        if old && !referenced.contains(&name) {
+            tracing::debug!("Removing orphaned {name}");
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    Ok(())
+}
+
+/// The `source` of every attachment of a result or container, including those of its steps.
+fn collect_sources(json: &Value, sources: &mut HashSet<String>) {
+    match json {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                match value {
+                    Value::String(source) if name == "source" => {
+                        sources.insert(source.clone());
+                    }
+                    value => collect_sources(value, sources),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_sources(v, sources)),
+        _ => {}
+    }
+}
+
+/// An advisory lock on the file at a path, held while merging into a file of the results
+/// directory, test binaries running at the same time wait for each other. The operating system
+/// releases the lock when it is dropped, also when the test binary is killed. The file itself is
+/// kept, removing it would let another binary lock a new file while the old one is locked.
+pub(crate) struct LockFile {
+    _file: File,
+}
+
+impl LockFile {
+    /// Blocks until the lock is acquired.
+    pub(crate) fn acquire(path: &Path) -> std::io::Result<LockFile> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        file.lock()?;
+        Ok(LockFile { _file: file })
+    }
+}
+
+/// Runs `f`, which may block, on the blocking threads of the current tokio runtime or else on
+/// this thread. The runtime waits for `f` before it shuts down.
+pub(crate) fn run_blocking(f: impl FnOnce() + Send + 'static) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => drop(runtime.spawn_blocking(f)),
+        Err(_) => f(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{remove_orphans, write_atomic, write_atomic_async, LockFile};
+    use crate::test_support::TempDir;
+    use std::fs::File;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    fn names(dir: &std::path::Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_write_atomic() {
+        let temp_dir = TempDir::new("write-atomic");
+        let dir = temp_dir.path();
+
+        write_atomic(&dir.join("a-result.json"), b"{}", false).unwrap();
+        write_atomic(&dir.join("a-result.json"), b"[]", true).unwrap();
+        write_atomic_async(&dir.join("b-result.json"), b"{}", false)
+            .await
+            .unwrap();
+        write_atomic_async(&dir.join("b-result.json"), b"[]", true)
+            .await
+            .unwrap();
+
+        assert_eq!(names(dir), ["a-result.json", "b-result.json"]);
+        assert_eq!(std::fs::read(dir.join("a-result.json")).unwrap(), b"[]");
+        assert_eq!(std::fs::read(dir.join("b-result.json")).unwrap(), b"[]");
+    }
+
+    #[test]
+    fn test_remove_orphans() {
+        let temp_dir = TempDir::new("remove-orphans");
+        let dir = temp_dir.path();
+        let write = |name: &str, content: &str, age: u64| {
+            std::fs::write(dir.join(name), content).unwrap();
+            File::options()
+                .write(true)
+                .open(dir.join(name))
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(age))
+                .unwrap();
+        };
+        write(
+            "a-result.json",
+            r#"{"attachments": [{"source": "test-attachment.txt"}],
+                "steps": [{"attachments": [{"source": "step-attachment.json"}]}]}"#,
+            120,
+        );
+        write("b-container.json", "truncated", 120);
+        write("test-attachment.txt", "", 120);
+        write("step-attachment.json", "", 120);
+        // Of a test still running in another test binary, or of one which was killed
+        write("running-attachment.txt", "", 7200);
+        write("new-attachment.txt", "", 0);
+        write(".c-result.json.0190.tmp", "", 7200);
+        write(".d-result.json.0190.tmp", "", 0);
+        write("environment.properties", "", 7200);
+
+        // By default only temporary files are removed
+        remove_orphans(dir, None).unwrap();
+        assert_eq!(
+            names(dir),
+            [
+                ".d-result.json.0190.tmp",
+                "a-result.json",
+                "b-container.json",
+                "environment.properties",
+                "new-attachment.txt",
+                "running-attachment.txt",
+                "step-attachment.json",
+                "test-attachment.txt",
+            ]
+        );
+
+        remove_orphans(dir, Some(Duration::from_secs(60))).unwrap();
+        assert_eq!(
+            names(dir),
+            [
+                ".d-result.json.0190.tmp",
+                "a-result.json",
+                "b-container.json",
+                "environment.properties",
+                "new-attachment.txt",
+                "step-attachment.json",
+                "test-attachment.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lock_file() {
+        let temp_dir = TempDir::new("lock-file");
+        let dir = temp_dir.path();
+        let path = dir.join(".file.lock");
+
+        let lock = LockFile::acquire(&path).unwrap();
+        let acquired = Arc::new(AtomicBool::new(false));
+        let waiting = std::thread::spawn({
+            let (path, acquired) = (path.clone(), acquired.clone());
+            move || {
+                let _lock = LockFile::acquire(&path).unwrap();
+                acquired.store(true, Ordering::SeqCst);
+            }
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!acquired.load(Ordering::SeqCst), "the lock is held");
+        drop(lock);
+        waiting.join().unwrap();
+        assert!(acquired.load(Ordering::SeqCst));
+        assert!(path.exists(), "the lock file is kept");
+    }
+}
@@ -1,27 +1,52 @@
 mod asserter;
+mod categories;
+pub mod config;
+mod context;
+mod environment;
 mod helpers;
+mod merged_file;
 pub mod middleware;
 pub mod reporter;
+mod step;
+#[doc(hidden)]
+pub mod step_error;
+#[cfg(test)]
+mod test_support;
+mod unwind;
 
 pub mod models {
     pub use allure_models::*;
 }
 
 use crate::asserter::{Asserter, WithoutThing};
-use crate::helpers::write_attachment;
+use crate::config::Config;
+use crate::helpers::{
+    write_atomic, write_atomic_async, write_attachment, write_attachment_blocking,
+};
 use crate::reporter::Mime;
-use allure_models::{Attachment, Status, TestResult};
+use allure_models::{
+    Attachment, FixtureKind, Label, Link, Parameter, Status, StatusDetails, StepId, TestResult,
+    TestResultContainer,
+};
 use anyhow::anyhow;
 use reporter::Message;
 use reqwest_middleware::ClientWithMiddleware;
 use std::fmt::Debug;
 
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-pub use allure_macros::{allure_step, allure_test};
+pub use allure_macros::{allure_after, allure_before, allure_step, allure_test};
+pub use asserter::AssertionError;
+pub use categories::add_category;
+pub use context::{attach, in_current_test, spawn};
+pub use environment::set_environment;
+pub use step::StepOutcome;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot;
-use uuid::Uuid;
+pub use unwind::{___private_catch_unwind, ___private_catch_unwind_sync, CaughtPanic};
 
 pub mod prelude {
     pub use anyhow;
@@ -30,12 +55,64 @@ pub mod prelude {
     pub use tokio;
 }
 
+/// Maps an error returned by a step or test to its Allure status. Errors caused by an
+/// [`AssertionError`] are reported as `failed`, any other error as `broken`. The details hold the
+/// whole source chain and, if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set, the backtrace.
+pub fn error_status(err: &anyhow::Error) -> (Status, StatusDetails) {
+    let status = if err.chain().any(|e| e.is::<AssertionError>()) {
+        Status::Failed
+    } else {
+        Status::Broken
+    };
+    let details = StatusDetails {
+        message: Some(format!("{err:#}")),
+        trace: Some(format!("{err:?}")),
+        ..Default::default()
+    };
+    (status, details)
+}
+
+/// Fails with a `broken` error if `fut` does not complete within `timeout`.
+pub async fn ___private_timeout<F, T>(timeout: Duration, fut: F) -> anyhow::Result<T>
+where
+    F: std::future::Future<Output = anyhow::Result<T>>,
+{
+    tokio::time::timeout(timeout, fut)
+        .await
+        .map_err(|_| anyhow!("Test timed out after {timeout:?}."))?
+}
+
+/// Reports to a test. Clones report to the same test and start their steps in the step open on
+/// this helper, so steps running concurrently each need their own clone.
 pub struct TestHelper {
     tx: UnboundedSender<Message>,
-    result_rx: Option<oneshot::Receiver<TestResult>>,
+    result_rx: Option<oneshot::Receiver<(TestResult, Option<TestResultContainer>)>>,
     result: Option<TestResult>,
-    allure_dir: String,
+    container: Option<TestResultContainer>,
+    config: Arc<Config>,
     client: ClientWithMiddleware,
+    /// The steps opened on this helper and not finalized yet, innermost last.
+    steps: Vec<StepId>,
+}
+
+impl Clone for TestHelper {
+    /// The clone cannot fetch or write the test's result.
+    fn clone(&self) -> Self {
+        TestHelper {
+            tx: self.tx.clone(),
+            result_rx: None,
+            result: None,
+            container: None,
+            config: self.config.clone(),
+            client: self.client.clone(),
+            steps: self.steps.clone(),
+        }
+    }
+}
+
+fn next_step_id() -> StepId {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    StepId(NEXT.fetch_add(1, Ordering::Relaxed))
 }
 
 impl TestHelper {
@@ -43,7 +120,7 @@ impl TestHelper {
         self.client.clone()
     }
 
-    pub fn asserter<Z, T>(&mut self) -> Asserter<Z, T, WithoutThing>
+    pub fn asserter<Z, T>(&mut self) -> Asserter<'_, Z, T, WithoutThing>
     where
         Z: PartialEq<T> + Debug,
         T: PartialEq<Z> + Debug,
@@ -58,7 +135,9 @@ impl TestHelper {
 
         self.tx.send(Message::Result)?;
         if let Some(rx) = self.result_rx.take() {
-            self.result = Some(rx.await?);
+            let (result, container) = rx.await?;
+            self.result = Some(result);
+            self.container = container;
         }
 
         Ok(self.result.as_ref().unwrap())
@@ -74,19 +153,135 @@ impl TestHelper {
             let expected = serde_json::to_string_pretty(&expected).unwrap();
             let actual = serde_json::to_string_pretty(&actual).unwrap();
             let diff = similar::TextDiff::from_lines(&expected, &actual);
-            Err(anyhow!(diff.unified_diff().to_string()))
+            Err(AssertionError {
+                description: "JSON equality comparison.".to_string(),
+                diff: diff.unified_diff().to_string(),
+            }
+            .into())
         }
     }
 
     // TODO: add description?
-    pub async fn ___private_start_step(&mut self, name: &str) -> anyhow::Result<()> {
-        self.tx.send(Message::StartStep(name.into()))?;
-        Ok(())
+    pub async fn ___private_start_step(
+        &mut self,
+        name: &str,
+        parameters: Vec<Parameter>,
+    ) -> anyhow::Result<()> {
+        self.___private_start_step_sync(name, parameters)
     }
 
     // TODO: add fields?
-    pub async fn ___private_finalize_step(&mut self, status: Status) -> anyhow::Result<()> {
-        self.tx.send(Message::FinalizeStep(status))?;
+    pub async fn ___private_finalize_step(
+        &mut self,
+        status: Status,
+        status_details: Option<StatusDetails>,
+    ) -> anyhow::Result<()> {
+        self.___private_finalize_step_sync(status, status_details)
+    }
+
+    /// Starts a step in the innermost step open on this helper.
+    pub fn ___private_start_step_sync(
+        &mut self,
+        name: &str,
+        parameters: Vec<Parameter>,
+    ) -> anyhow::Result<()> {
+        let id = next_step_id();
+        self.tx.send(Message::StartStep(
+            id,
+            self.current_step(),
+            name.into(),
+            parameters,
+        ))?;
+        self.steps.push(id);
+        Ok(())
+    }
+
+    /// Finalizes the innermost step or fixture open on this helper.
+    pub fn ___private_finalize_step_sync(
+        &mut self,
+        status: Status,
+        status_details: Option<StatusDetails>,
+    ) -> anyhow::Result<()> {
+        if let Some(id) = self.steps.pop() {
+            self.tx
+                .send(Message::FinalizeStep(id, status, status_details))?;
+        }
+        Ok(())
+    }
+
+    pub async fn ___private_start_fixture(
+        &mut self,
+        kind: FixtureKind,
+        name: &str,
+        parameters: Vec<Parameter>,
+    ) -> anyhow::Result<()> {
+        self.___private_start_fixture_sync(kind, name, parameters)
+    }
+
+    pub fn ___private_start_fixture_sync(
+        &mut self,
+        kind: FixtureKind,
+        name: &str,
+        parameters: Vec<Parameter>,
+    ) -> anyhow::Result<()> {
+        let id = next_step_id();
+        self.tx
+            .send(Message::StartFixture(id, kind, name.into(), parameters))?;
+        self.steps.push(id);
+        Ok(())
+    }
+
+    /// The innermost step opened on this helper.
+    fn current_step(&self) -> Option<StepId> {
+        self.steps.last().copied()
+    }
+
+    pub async fn ___private_set_status(
+        &mut self,
+        status: Status,
+        status_details: Option<StatusDetails>,
+    ) -> anyhow::Result<()> {
+        self.tx.send(Message::SetStatus(status, status_details))?;
+        Ok(())
+    }
+
+    /// Adds a parameter to the test, parameters which are not excluded make up the history id
+    /// together with the test's full name.
+    pub fn parameter(&mut self, parameter: Parameter) -> anyhow::Result<()> {
+        self.tx.send(Message::AddParameter(parameter))?;
+        Ok(())
+    }
+
+    pub fn label(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        self.tx.send(Message::AddLabel(Label::new(name, value)))?;
+        Ok(())
+    }
+
+    pub fn link(&mut self, name: &str, url: &str) -> anyhow::Result<()> {
+        self.tx
+            .send(Message::AddLink(Link::new("link", name, url)))?;
+        Ok(())
+    }
+
+    pub fn issue(&mut self, name: &str) -> anyhow::Result<()> {
+        self.tx.send(Message::AddLink(Link::named("issue", name)))?;
+        Ok(())
+    }
+
+    /// Sets the test's description, Allure renders it as Markdown.
+    pub fn description(&mut self, description: &str) -> anyhow::Result<()> {
+        self.tx.send(Message::SetDescription(description.into()))?;
+        Ok(())
+    }
+
+    pub fn description_html(&mut self, html: &str) -> anyhow::Result<()> {
+        self.tx.send(Message::SetDescriptionHtml(html.into()))?;
+        Ok(())
+    }
+
+    /// Changes the name shown in the report, the test's identity stays the same.
+    pub fn rename(&mut self, name: &str) -> anyhow::Result<()> {
+        self.tx.send(Message::SetName(name.into()))?;
         Ok(())
     }
 
@@ -96,23 +291,217 @@ impl TestHelper {
         mime: Mime,
         content: &[u8],
     ) -> anyhow::Result<()> {
-        let of = write_attachment(mime, content, self.allure_dir.as_str().into()).await?;
-        self.tx.send(Message::AddAttachment(Attachment {
-            name: name.into(),
-            source: of,
-            r#type: mime.to_string().to_string(),
-        }))?;
+        let of = write_attachment(mime, content, &self.config).await?;
+        self.tx.send(Message::AddAttachment(
+            self.current_step(),
+            Attachment {
+                name: name.into(),
+                source: of,
+                r#type: mime.to_string().to_string(),
+            },
+        ))?;
+        Ok(())
+    }
+
+    /// Like [`TestHelper::attachment`], for synchronous tests and steps.
+    pub fn attachment_sync(
+        &mut self,
+        name: &str,
+        mime: Mime,
+        content: &[u8],
+    ) -> anyhow::Result<()> {
+        let of = write_attachment_blocking(mime, content, &self.config)?;
+        self.tx.send(Message::AddAttachment(
+            self.current_step(),
+            Attachment {
+                name: name.into(),
+                source: of,
+                r#type: mime.to_string().to_string(),
+            },
+        ))?;
+        Ok(())
+    }
+
+    /// Records the outcome of the test body and writes the result, then fails the test if the
+    /// body returned an error or re-raises its panic. Steps left open by a panic, timeout or
+    /// error are finalized with the test's status.
+    pub async fn ___private_finish(&mut self, outcome: Result<anyhow::Result<()>, CaughtPanic>) {
+        self.record_outcome(&outcome);
+        let written = match self.___private_fetch_result().await {
+            Ok(_) => self.___private_write_result().await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            tracing::error!("Failed to write the allure result: {err:?}");
+        }
+        Self::raise(outcome)
+    }
+
+    /// Like [`TestHelper::___private_finish`] for synchronous tests, whose reporter runs on its
+    /// own thread with [`reporter::Reporter::task_blocking`].
+    pub fn ___private_finish_blocking(&mut self, outcome: Result<anyhow::Result<()>, CaughtPanic>) {
+        self.record_outcome(&outcome);
+        let written = self.fetch_result_blocking().and_then(|()| {
+            for (path, content) in self.result_files()? {
+                write_atomic(&path, content.as_bytes(), self.config.fsync)?;
+            }
+            Ok(())
+        });
+        if let Err(err) = written {
+            tracing::error!("Failed to write the allure result: {err:?}");
+        }
+        Self::raise(outcome)
+    }
+
+    fn record_outcome(&self, outcome: &Result<anyhow::Result<()>, CaughtPanic>) {
+        let status = match outcome {
+            Ok(Ok(())) => None,
+            Ok(Err(err)) => Some(error_status(err)),
+            Err(panic) => Some(panic.status()),
+        };
+        if let Some((status, details)) = status {
+            let _ = self
+                .tx
+                .send(Message::FinalizeOpenSteps(status, Some(details.clone())));
+            let _ = self.tx.send(Message::SetStatus(status, Some(details)));
+        }
+    }
+
+    fn raise(outcome: Result<anyhow::Result<()>, CaughtPanic>) {
+        match outcome {
+            Ok(res) => res.expect("Test failed."),
+            Err(panic) => panic.resume(),
+        }
+    }
+
+    fn fetch_result_blocking(&mut self) -> anyhow::Result<()> {
+        self.tx.send(Message::Result)?;
+        if let Some(rx) = self.result_rx.take() {
+            let (result, container) = rx.blocking_recv()?;
+            self.result = Some(result);
+            self.container = container;
+        }
         Ok(())
     }
 
     pub async fn ___private_write_result(&self) -> anyhow::Result<()> {
-        if let Some(r) = self.result.as_ref() {
-            let mut target_dir = PathBuf::from(&self.allure_dir);
-            target_dir.push(format!("{}-result.json", Uuid::now_v7()));
-            tokio::fs::write(target_dir, serde_json::to_string(r).unwrap()).await?;
-        } else {
-            anyhow::bail!("Result is not fetched, fetch result before trying to write it.");
+        for (path, content) in self.result_files()? {
+            write_atomic_async(&path, content.as_bytes(), self.config.fsync).await?;
         }
         Ok(())
     }
+
+    /// The paths and contents of the result file and, if any fixtures ran, the container file.
+    fn result_files(&self) -> anyhow::Result<Vec<(PathBuf, String)>> {
+        let Some(r) = self.result.as_ref() else {
+            anyhow::bail!("Result is not fetched, fetch result before trying to write it.");
+        };
+        let mut files = vec![(
+            self.config
+                .results_dir
+                .join(format!("{}-result.json", r.uuid)),
+            serde_json::to_string(r).unwrap(),
+        )];
+        if let Some(c) = self.container.as_ref() {
+            files.push((
+                self.config
+                    .results_dir
+                    .join(format!("{}-container.json", c.uuid)),
+                serde_json::to_string(c).unwrap(),
+            ));
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ___private_catch_unwind, ___private_catch_unwind_sync, error_status, AssertionError,
+    };
+    use crate::config::Config;
+    use crate::test_support::{start_test, TempDir};
+    use allure_models::{Status, TestResult};
+    use anyhow::Context;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_result_is_written_on_panic() {
+        let allure_dir = TempDir::new("result-on-panic");
+        let (mut helper, _task) = start_test(Config::new(allure_dir.path()));
+
+        let outcome = ___private_catch_unwind(async {
+            helper.___private_start_step("step", vec![]).await?;
+            // The test may resume on another worker after the panic is caught
+            tokio::task::yield_now().await;
+            panic!("boom");
+        })
+        .await;
+        let finished = ___private_catch_unwind(helper.___private_finish(outcome)).await;
+        assert_eq!(
+            finished.unwrap_err().status().1.message.as_deref(),
+            Some("boom"),
+            "the original panic is re-raised"
+        );
+
+        let file = std::fs::read_dir(allure_dir.path())
+            .unwrap()
+            .map(|f| f.unwrap().path())
+            .find(|p| p.to_str().unwrap().ends_with("-result.json"))
+            .unwrap();
+        let result: TestResult = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        assert_eq!(result.status, Status::Broken);
+        let details = result.status_details.unwrap();
+        assert_eq!(details.message.unwrap(), "boom");
+        assert!(details
+            .trace
+            .unwrap()
+            .contains("test_result_is_written_on_panic"));
+        assert_eq!(result.steps[0].status, Status::Broken);
+    }
+
+    #[test]
+    fn test_panic_status() {
+        super::unwind::install_panic_hook();
+        let panic = ___private_catch_unwind_sync(|| assert_eq!(1, 2)).unwrap_err();
+        let (status, details) = panic.status();
+        assert_eq!(status, Status::Failed);
+        assert!(details
+            .message
+            .unwrap()
+            .starts_with("assertion `left == right` failed"));
+        assert!(details.trace.unwrap().contains("test_panic_status"));
+
+        let panic = ___private_catch_unwind_sync(|| "x".parse::<u8>().unwrap()).unwrap_err();
+        let (status, details) = panic.status();
+        assert_eq!(status, Status::Broken);
+        assert_eq!(
+            details.message.as_deref(),
+            Some(
+                "called `Result::unwrap()` on an `Err` value: ParseIntError { kind: InvalidDigit }"
+            )
+        );
+    }
+
+    #[test]
+    fn test_error_status() {
+        let err = anyhow::Error::from(AssertionError {
+            description: "equal".into(),
+            diff: "-a\n+b".into(),
+        })
+        .context("step failed");
+        let (status, details) = error_status(&err);
+        assert_eq!(status, Status::Failed);
+        assert_eq!(
+            details.message.as_deref(),
+            Some("step failed: Assertion failed: equal\n-a\n+b")
+        );
+
+        let err = Err::<(), _>(std::io::Error::other("refused"))
+            .context("request failed")
+            .unwrap_err();
+        let (status, details) = error_status(&err);
+        assert_eq!(status, Status::Broken);
+        assert_eq!(details.message.as_deref(), Some("request failed: refused"));
+        assert!(details.trace.unwrap().contains("Caused by"));
+    }
 }
@@ -0,0 +1,99 @@
+//! Files of the results directory which all test binaries of a run contribute to, such as
+//! `environment.properties`. Each test binary writes its values when its first test starts, into
+//! that test's results directory, and again when values are added later. The values are merged
+//! with the existing file under a lock file, and the file is replaced by renaming a temporary
+//! file.
+use crate::config::Config;
+use crate::helpers::{run_blocking, write_atomic, LockFile};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The content of the file with the values merged into the existing content, if there is a file.
+pub(crate) type Merge<T> = fn(&T, Option<Vec<u8>>) -> anyhow::Result<Vec<u8>>;
+
+pub(crate) struct MergedFile<T: 'static> {
+    name: &'static str,
+    merge: Merge<T>,
+    state: Mutex<State<T>>,
+    /// Held while writing, so the file is written once at a time, each time with the latest
+    /// values.
+    writing: Mutex<()>,
+}
+
+struct State<T> {
+    /// The results directory, once the first test started.
+    dir: Option<PathBuf>,
+    fsync: bool,
+    values: T,
+}
+
+impl<T: Clone + Send> MergedFile<T> {
+    pub(crate) const fn new(name: &'static str, values: T, merge: Merge<T>) -> Self {
+        MergedFile {
+            name,
+            merge,
+            state: Mutex::new(State {
+                dir: None,
+                fsync: false,
+                values,
+            }),
+            writing: Mutex::new(()),
+        }
+    }
+
+    /// Changes the values with `update`, they are written if the first test started.
+    pub(crate) fn update(&'static self, update: impl FnOnce(&mut T)) {
+        let mut state = self.state.lock().unwrap();
+        update(&mut state.values);
+        if state.dir.is_some() {
+            drop(state);
+            self.write_latest();
+        }
+    }
+
+    /// Completes the values with `init` and writes them into the config's results directory, if
+    /// this binary has not yet. Returns whether it had not.
+    pub(crate) fn write_once(&'static self, config: &Config, init: impl FnOnce(&mut T)) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.dir.is_some() {
+            return false;
+        }
+        init(&mut state.values);
+        state.dir = Some(config.results_dir.clone());
+        state.fsync = config.fsync;
+        drop(state);
+        self.write_latest();
+        true
+    }
+
+    /// Writes the latest values with [`run_blocking`], as other test binaries may hold the lock
+    /// of the file.
+    fn write_latest(&'static self) {
+        run_blocking(move || {
+            let _writing = self.writing.lock().unwrap();
+            let state = self.state.lock().unwrap();
+            let (dir, values, fsync) = match &state.dir {
+                Some(dir) => (dir.clone(), state.values.clone(), state.fsync),
+                None => return,
+            };
+            drop(state);
+            if let Err(err) = self.write(&dir, &values, fsync) {
+                tracing::warn!("Failed to write {}: {err}", self.name);
+            }
+        });
+    }
+
+    /// Merges `values` into the file in `dir`.
+    pub(crate) fn write(&self, dir: &Path, values: &T, fsync: bool) -> anyhow::Result<()> {
+        let path = dir.join(self.name);
+        let _lock = LockFile::acquire(&dir.join(format!(".{}.lock", self.name)))?;
+        let existing = match std::fs::read(&path) {
+            Ok(content) => Some(content),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        write_atomic(&path, &(self.merge)(values, existing)?, fsync)?;
+        Ok(())
+    }
+}
@@ -1,15 +1,20 @@
+use crate::config::{Config, REDACTED};
 use crate::reporter::{Message, Mime};
+use crate::TestHelper;
 use allure_models::Attachment;
 use http::HeaderMap;
 use reqwest::{Request, Response};
 use reqwest_middleware::{Middleware, Next, Result};
 use serde_json::Value;
 use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc::UnboundedSender;
 
+/// Attaches the requests made by a test's client and their responses to the test, with the
+/// headers and JSON fields named by the config's redaction rules hidden.
 pub struct AllureConnectorMiddleware {
-    allure_dir: PathBuf,
+    config: Arc<Config>,
     tx: UnboundedSender<Message>,
 }
 
@@ -33,27 +38,65 @@ impl Middleware for AllureConnectorMiddleware {
 }
 
 impl AllureConnectorMiddleware {
-    pub fn new(allure_dir: PathBuf, tx: UnboundedSender<Message>) -> Self {
-        if !allure_dir.exists() {
-            std::fs::create_dir_all(&allure_dir).unwrap();
+    pub fn new(config: Arc<Config>, tx: UnboundedSender<Message>) -> Self {
+        if !config.results_dir.exists() {
+            std::fs::create_dir_all(&config.results_dir).unwrap();
+        }
+        Self { config, tx }
+    }
+
+    fn header_lines(&self, headers: &HeaderMap) -> Vec<String> {
+        headers
+            .iter()
+            .map(|(k, v)| {
+                let redact = self
+                    .config
+                    .redact
+                    .headers
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(k.as_str()));
+                if redact {
+                    format!("{k}: {REDACTED}")
+                } else {
+                    format!("{}: {}", k, String::from_utf8_lossy(v.as_bytes()))
+                }
+            })
+            .collect()
+    }
+
+    fn redact_fields(&self, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (name, value) in fields.iter_mut() {
+                    if self.config.redact.fields.contains(name) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_fields(value);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|v| self.redact_fields(v)),
+            _ => {}
         }
-        Self { allure_dir, tx }
     }
 
     #[tracing::instrument(skip(self, content))]
     async fn add_attachment(&self, name: &str, mime: Mime, content: Vec<u8>) -> anyhow::Result<()> {
         let of_name = self.write_attachment(mime, &content).await?;
 
-        self.tx.send(Message::AddAttachment(Attachment {
-            name: name.to_string(),
-            source: of_name,
-            r#type: mime.to_string(),
-        }))?;
+        self.tx.send(Message::AddAttachment(
+            TestHelper::current_step_id(),
+            Attachment {
+                name: name.to_string(),
+                source: of_name,
+                r#type: mime.to_string(),
+            },
+        ))?;
         Ok(())
     }
 
     async fn write_attachment(&self, mime: Mime, content: &[u8]) -> anyhow::Result<PathBuf> {
-        crate::helpers::write_attachment(mime, content, self.allure_dir.clone()).await
+        crate::helpers::write_attachment(mime, content, &self.config).await
     }
 
     async fn prepare_response_copy(res: Response) -> Result<(bytes::Bytes, HeaderMap, Response)> {
@@ -68,12 +111,10 @@ impl AllureConnectorMiddleware {
     }
 
     async fn log_response(&self, headers: HeaderMap, body: bytes::Bytes) -> anyhow::Result<()> {
-        let headers = headers
-            .iter()
-            .map(|(k, v)| format!("{}: {}", k, String::from_utf8_lossy(v.as_bytes())))
-            .collect::<Vec<String>>();
+        let headers = self.header_lines(&headers);
 
-        let body_v = if let Ok(jsn) = serde_json::from_slice(&body) {
+        let body_v = if let Ok(mut jsn) = serde_json::from_slice(&body) {
+            self.redact_fields(&mut jsn);
             jsn
         } else {
             Value::String(String::from_utf8_lossy(&body).to_string())
@@ -96,7 +137,8 @@ impl AllureConnectorMiddleware {
 
     async fn log_request(&self, req: &Request) -> anyhow::Result<()> {
         let body = if let Some(mut body) = req.body().and_then(|b| b.as_bytes()) {
-            if let Ok(json) = serde_json::from_reader(&mut body) {
+            if let Ok(mut json) = serde_json::from_reader(&mut body) {
+                self.redact_fields(&mut json);
                 json
             } else {
                 Value::String(String::from_utf8_lossy(body).to_string())
@@ -105,11 +147,7 @@ impl AllureConnectorMiddleware {
             Value::String(String::new())
         };
 
-        let headers = req
-            .headers()
-            .iter()
-            .map(|(k, v)| format!("{}: {}", k, String::from_utf8_lossy(v.as_bytes())))
-            .collect::<Vec<String>>();
+        let headers = self.header_lines(req.headers());
 
         let mut buf = Vec::new();
         for header in headers.into_iter() {
@@ -134,3 +172,38 @@ impl AllureConnectorMiddleware {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::AllureConnectorMiddleware;
+    use crate::config::Config;
+    use crate::test_support::TempDir;
+    use http::{HeaderMap, HeaderValue};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_redaction() {
+        let dir = TempDir::new("redaction");
+        let mut config = Config::new(dir.path());
+        config.redact.headers = vec!["Authorization".into()];
+        config.redact.fields = vec!["password".into()];
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let middleware = AllureConnectorMiddleware::new(Arc::new(config), tx);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        assert_eq!(
+            middleware.header_lines(&headers),
+            ["authorization: [REDACTED]", "accept: */*"]
+        );
+
+        let mut body = json!({"user": "alice", "password": "x", "nested": [{"password": "y"}]});
+        middleware.redact_fields(&mut body);
+        assert_eq!(
+            body,
+            json!({"user": "alice", "password": "[REDACTED]", "nested": [{"password": "[REDACTED]"}]})
+        );
+    }
+}
@@ -1,89 +1,233 @@
+use crate::config::Config;
 use crate::middleware::AllureConnectorMiddleware;
 use crate::TestHelper;
-use allure_models::{Attachment, Status, TestResult, TestResultBuilder};
+use allure_models::{
+    Attachment, FixtureKind, Label, Link, Parameter, Status, StatusDetails, StepId, TestResult,
+    TestResultBuilder, TestResultContainer,
+};
 use reqwest::Client;
 use reqwest_middleware::ClientBuilder;
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
+use std::sync::Arc;
 
 pub struct Reporter {
     test: TestResultBuilder,
     rx: tokio::sync::mpsc::UnboundedReceiver<Message>,
-    result_tx: tokio::sync::oneshot::Sender<TestResult>,
+    result_tx: tokio::sync::oneshot::Sender<(TestResult, Option<TestResultContainer>)>,
+    config: Arc<Config>,
+    /// Names of the labels added from the config which the test has not overridden yet.
+    default_labels: Vec<String>,
 }
 
+/// Messages are applied in the order they were sent. Steps are identified by their [`StepId`], so
+/// messages of concurrently running steps may interleave. Anything sent after [`Message::Result`]
+/// is dropped.
 #[derive(Debug)]
 pub enum Message {
-    StartStep(String),
-    FinalizeStep(Status),
-    AddAttachment(Attachment),
+    /// Starts a step with the id, parent step, name and parameters.
+    StartStep(StepId, Option<StepId>, String, Vec<Parameter>),
+    /// Finalizes a step or fixture.
+    FinalizeStep(StepId, Status, Option<StatusDetails>),
+    /// Starts a fixture, steps started in it belong to it.
+    StartFixture(StepId, FixtureKind, String, Vec<Parameter>),
+    /// Finalizes all open steps and fixtures, sent when the test ended without finalizing them.
+    FinalizeOpenSteps(Status, Option<StatusDetails>),
+    SetStatus(Status, Option<StatusDetails>),
+    /// Adds an attachment to the step, or to the test if it has no open step.
+    AddAttachment(Option<StepId>, Attachment),
+    /// Adds a parameter to the test, step parameters are passed with [`Message::StartStep`].
+    AddParameter(Parameter),
+    AddLabel(Label),
+    AddLink(Link),
+    SetDescription(String),
+    SetDescriptionHtml(String),
+    SetName(String),
     Result,
 }
 
 impl Reporter {
-    pub fn new(name: &str, full_name: &str, suite: &str, allure_dir: &str) -> (Self, TestHelper) {
-        let test_builder = TestResultBuilder::new(name, full_name, suite);
+    pub fn new(
+        name: &str,
+        full_name: &str,
+        module_path: &str,
+        allure_dir: &str,
+    ) -> (Self, TestHelper) {
+        Self::with_config(name, full_name, module_path, Config::new(allure_dir))
+    }
+
+    /// A reporter writing to the config's results directory. `module_path` is the test's
+    /// `module_path!()`, the test gets the labels derived from it, then the config's labels, both
+    /// replaced by labels of the same name the test adds. Its links are completed with the
+    /// config's link patterns.
+    pub fn with_config(
+        name: &str,
+        full_name: &str,
+        module_path: &str,
+        config: Config,
+    ) -> (Self, TestHelper) {
+        crate::unwind::install_panic_hook();
+        let config = Arc::new(config);
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
         let (result_tx, result_rx) = tokio::sync::oneshot::channel();
         let reqwest_client = Client::builder().build().unwrap();
 
         let client = ClientBuilder::new(reqwest_client)
-            .with(AllureConnectorMiddleware::new(
-                PathBuf::from(allure_dir),
-                tx.clone(),
-            ))
+            .with(AllureConnectorMiddleware::new(config.clone(), tx.clone()))
             .build();
+        crate::environment::write_once(&config);
+        crate::categories::write_once(&config);
+        crate::helpers::remove_orphans_once(&config);
+        let mut reporter = Self {
+            test: TestResultBuilder::new(name, full_name, module_path),
+            rx,
+            result_tx,
+            config: config.clone(),
+            default_labels: vec![],
+        };
+        let config_labels = config
+            .labels
+            .iter()
+            .map(|(name, value)| Label::new(name, value));
+        for label in automatic_labels(full_name, module_path).chain(config_labels) {
+            reporter.add_default_label(label);
+        }
         (
-            Self {
-                test: test_builder,
-                rx,
-                result_tx,
-            },
+            reporter,
             TestHelper {
                 tx,
                 result_rx: Some(result_rx),
                 result: None,
-                allure_dir: allure_dir.into(),
+                container: None,
+                config,
                 client,
+                steps: vec![],
             },
         )
     }
 
     pub async fn task(mut self) -> anyhow::Result<()> {
         while let Some(message) = self.rx.recv().await {
-            tracing::debug!("Received message {:?}", message);
-
-            match message {
-                Message::StartStep(name) => self.start_step(&name)?,
-                Message::FinalizeStep(status) => self.finalize_step(status),
-                Message::AddAttachment(attachment) => {
-                    if let Some(cs) = self.test.current_step.as_mut() {
-                        cs.attachments.push(attachment)
-                    }
-                }
-                Message::Result => {
-                    let Self {
-                        test,
-                        rx: _,
-                        result_tx,
-                    } = self;
-                    let result = test.build();
-                    result_tx.send(result).unwrap();
-                    tracing::info!("Exiting");
-                    break;
-                }
+            if self.handle(message) {
+                self.send_result();
+                break;
             }
         }
         Ok(())
     }
 
-    pub fn start_step(&mut self, name: &str) -> anyhow::Result<()> {
-        let _ = self.test.start_step(name)?;
+    /// Like [`Reporter::task`], for synchronous tests which run it on their own thread.
+    pub fn task_blocking(mut self) -> anyhow::Result<()> {
+        while let Some(message) = self.rx.blocking_recv() {
+            if self.handle(message) {
+                self.send_result();
+                break;
+            }
+        }
         Ok(())
     }
 
-    pub fn finalize_step(&mut self, status: Status) {
-        self.test.finalize_step(status)
+    /// Applies the message, returns whether the result was requested.
+    fn handle(&mut self, message: Message) -> bool {
+        tracing::debug!("Received message {:?}", message);
+
+        match message {
+            Message::StartStep(id, parent, name, parameters) => {
+                self.start_step(id, parent, &name, parameters)
+            }
+            Message::FinalizeStep(id, status, details) => self.finalize_step(id, status, details),
+            Message::StartFixture(id, kind, name, parameters) => {
+                self.start_fixture(id, kind, &name, parameters)
+            }
+            Message::FinalizeOpenSteps(status, details) => {
+                self.test.finalize_open_steps(status, details)
+            }
+            Message::SetStatus(status, details) => self.test.set_status(status, details),
+            Message::AddAttachment(step, attachment) => self.add_attachment(step, attachment),
+            Message::AddParameter(parameter) => self.test.add_parameter(parameter),
+            Message::AddLabel(label) => self.add_label(label),
+            Message::AddLink(link) => self.add_link(link),
+            Message::SetDescription(description) => self.test.description = Some(description),
+            Message::SetDescriptionHtml(html) => self.test.description_html = Some(html),
+            Message::SetName(name) => self.test.name = name,
+            Message::Result => return true,
+        }
+        false
+    }
+
+    fn send_result(self) {
+        let Self {
+            test, result_tx, ..
+        } = self;
+        result_tx.send(test.build_with_container()).unwrap();
+        tracing::info!("Exiting");
+    }
+
+    pub fn start_step(
+        &mut self,
+        id: StepId,
+        parent: Option<StepId>,
+        name: &str,
+        parameters: Vec<Parameter>,
+    ) {
+        self.test.start_step(id, parent, name).parameters = parameters;
+    }
+
+    pub fn finalize_step(
+        &mut self,
+        id: StepId,
+        status: Status,
+        status_details: Option<StatusDetails>,
+    ) {
+        self.test.finalize_step(id, status, status_details)
+    }
+
+    pub fn start_fixture(
+        &mut self,
+        id: StepId,
+        kind: FixtureKind,
+        name: &str,
+        parameters: Vec<Parameter>,
+    ) {
+        self.test.start_fixture(id, kind, name).parameters = parameters;
+    }
+
+    /// Adds a label the test can replace, replacing labels of the same name.
+    fn add_default_label(&mut self, label: Label) {
+        self.test.labels.retain(|l| l.name != label.name);
+        if !self.default_labels.contains(&label.name) {
+            self.default_labels.push(label.name.clone());
+        }
+        self.test.add_label(label)
+    }
+
+    /// Adds the label, replacing an automatic or configured label of the same name.
+    pub fn add_label(&mut self, label: Label) {
+        if let Some(i) = self.default_labels.iter().position(|n| *n == label.name) {
+            self.default_labels.swap_remove(i);
+            self.test.labels.retain(|l| l.name != label.name);
+        }
+        self.test.add_label(label)
+    }
+
+    /// Adds the link, a link without a url gets it from the config's link pattern for its type,
+    /// if there is one.
+    pub fn add_link(&mut self, mut link: Link) {
+        if link.url.is_none() {
+            link.url = self.config.link_url(&link.r#type, &link.name);
+        }
+        self.test.add_link(link)
+    }
+
+    pub fn set_description(&mut self, description: &str) {
+        self.test.description = Some(description.into())
+    }
+
+    pub fn set_description_html(&mut self, html: &str) {
+        self.test.description_html = Some(html.into())
+    }
+
+    pub fn add_attachment(&mut self, step: Option<StepId>, attachment: Attachment) {
+        self.test.add_attachment(step, attachment)
     }
 
     pub fn get_result(self) -> TestResult {
@@ -91,6 +235,40 @@ impl Reporter {
     }
 }
 
+/// The labels allure-java and allure-pytest add by themselves, Allure's timeline, package and
+/// suites views are built from them. The suites are the crate, the module below it and the
+/// modules below that.
+fn automatic_labels(full_name: &str, module_path: &str) -> impl Iterator<Item = Label> {
+    let thread = std::thread::current();
+    let thread = match thread.name() {
+        Some(name) => format!("{}.{}", std::process::id(), name),
+        None => format!("{}.{:?}", std::process::id(), thread.id()),
+    };
+    let mut modules = module_path.splitn(3, "::");
+    let suites = match (modules.next(), modules.next(), modules.next()) {
+        (Some(krate), Some(module), sub) => vec![
+            Label::new("parentSuite", krate),
+            Label::new("suite", module),
+        ]
+        .into_iter()
+        .chain(sub.map(|sub| Label::new("subSuite", sub)))
+        .collect(),
+        _ => vec![Label::new("suite", module_path)],
+    };
+    let method = full_name.rsplit("::").next().unwrap_or(full_name);
+    [
+        Label::new("host", gethostname::gethostname().to_string_lossy()),
+        Label::new("thread", thread),
+        Label::new("language", "rust"),
+        Label::new("framework", "allure-rust"),
+        Label::new("package", module_path.replace("::", ".")),
+        Label::new("testClass", module_path),
+        Label::new("testMethod", method),
+    ]
+    .into_iter()
+    .chain(suites)
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Mime {
     ApplicationJson,
@@ -114,3 +292,204 @@ impl Mime {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Mime, Reporter};
+    use crate::config::Config;
+    use crate::test_support::{start_test, TempDir};
+    use allure_models::{Label, Link, Parameter, Status};
+
+    #[tokio::test]
+    async fn test_attachment_ordering() {
+        let allure_dir = TempDir::new("attachment-ordering");
+        let (mut helper, task) = start_test(Config::new(allure_dir.path()));
+
+        helper.attachment("before", Mime::Txt, b"").await.unwrap();
+        helper.___private_start_step("outer", vec![]).await.unwrap();
+        helper.attachment("outer 1", Mime::Txt, b"").await.unwrap();
+        helper.___private_start_step("inner", vec![]).await.unwrap();
+        helper.attachment("inner", Mime::Txt, b"").await.unwrap();
+        helper
+            .___private_finalize_step(Status::Passed, None)
+            .await
+            .unwrap();
+        helper.attachment("outer 2", Mime::Txt, b"").await.unwrap();
+        helper
+            .___private_finalize_step(Status::Passed, None)
+            .await
+            .unwrap();
+        helper.attachment("after", Mime::Txt, b"").await.unwrap();
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        let names = |attachments: &[allure_models::Attachment]| {
+            attachments
+                .iter()
+                .map(|a| a.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&result.attachments), ["before", "after"]);
+        assert_eq!(names(&result.steps[0].attachments), ["outer 1", "outer 2"]);
+        assert_eq!(names(&result.steps[0].steps[0].attachments), ["inner"]);
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_steps() {
+        let allure_dir = TempDir::new("concurrent-steps");
+        let (mut helper, task) = start_test(Config::new(allure_dir.path()));
+
+        helper
+            .___private_start_step("parallel", vec![])
+            .await
+            .unwrap();
+        let (mut first, mut second) = (helper.clone(), helper.clone());
+        first.___private_start_step("first", vec![]).await.unwrap();
+        second
+            .___private_start_step("second", vec![])
+            .await
+            .unwrap();
+        first.attachment("first", Mime::Txt, b"").await.unwrap();
+        second.attachment("second", Mime::Txt, b"").await.unwrap();
+        second
+            .___private_finalize_step(Status::Passed, None)
+            .await
+            .unwrap();
+        helper.attachment("parallel", Mime::Txt, b"").await.unwrap();
+        first
+            .___private_finalize_step(Status::Failed, None)
+            .await
+            .unwrap();
+        helper
+            .___private_finalize_step(Status::Passed, None)
+            .await
+            .unwrap();
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        let parallel = &result.steps[0];
+        assert_eq!(parallel.attachments[0].name, "parallel");
+        assert_eq!(parallel.steps[0].name, "first");
+        assert_eq!(parallel.steps[0].status, Status::Failed);
+        assert_eq!(parallel.steps[0].attachments[0].name, "first");
+        assert_eq!(parallel.steps[1].name, "second");
+        assert_eq!(parallel.steps[1].attachments[0].name, "second");
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_parameters() {
+        let allure_dir = TempDir::new("parameters");
+        let (mut helper, task) = start_test(Config::new(allure_dir.path()));
+
+        helper.parameter(Parameter::new("tenant", "acme")).unwrap();
+        helper
+            .___private_start_step("step", vec![Parameter::new("password", "x").masked()])
+            .await
+            .unwrap();
+        helper
+            .___private_finalize_step(Status::Passed, None)
+            .await
+            .unwrap();
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        assert_eq!(result.parameters, [Parameter::new("tenant", "acme")]);
+        assert_eq!(
+            result.steps[0].parameters,
+            [Parameter::new("password", "x").masked()]
+        );
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_runtime_metadata() {
+        let allure_dir = TempDir::new("runtime-metadata");
+        let (mut helper, task) = start_test(Config::new(allure_dir.path()));
+
+        helper.label("tenant", "acme").unwrap();
+        helper.link("Docs", "https://example.com").unwrap();
+        helper.issue("BUG-1").unwrap();
+        helper.description("*markdown*").unwrap();
+        helper.description_html("<b>html</b>").unwrap();
+        helper.rename("renamed").unwrap();
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        assert!(result.labels.contains(&Label::new("tenant", "acme")));
+        assert_eq!(
+            result.links,
+            [
+                Link::new("link", "Docs", "https://example.com"),
+                Link::named("issue", "BUG-1")
+            ]
+        );
+        assert_eq!(result.description.as_deref(), Some("*markdown*"));
+        assert_eq!(result.description_html.as_deref(), Some("<b>html</b>"));
+        assert_eq!(result.name, "renamed");
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_config_labels_and_links() {
+        let allure_dir = TempDir::new("config-labels-and-links");
+        let mut config = Config::new(allure_dir.path());
+        config.labels.insert("layer".into(), "api".into());
+        config.labels.insert("owner".into(), "qa".into());
+        config
+            .links
+            .insert("issue".into(), "https://issues.example.com/{}".into());
+        let (mut helper, task) = start_test(config);
+
+        helper.label("owner", "alice").unwrap();
+        helper.issue("BUG-1").unwrap();
+        helper.link("Docs", "Docs").unwrap();
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        assert!(result.labels.contains(&Label::new("layer", "api")));
+        assert!(result.labels.contains(&Label::new("owner", "alice")));
+        assert!(!result.labels.contains(&Label::new("owner", "qa")));
+        assert_eq!(
+            result.links,
+            [
+                Link::new("issue", "BUG-1", "https://issues.example.com/BUG-1"),
+                Link::new("link", "Docs", "Docs")
+            ]
+        );
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_automatic_labels() {
+        let allure_dir = TempDir::new("automatic-labels");
+        let (reporter, mut helper) = Reporter::with_config(
+            "name",
+            "krate::module::sub::test_fn",
+            "krate::module::sub",
+            Config::new(allure_dir.path()),
+        );
+        let task = tokio::spawn(reporter.task());
+
+        helper.label("subSuite", "custom").unwrap();
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        let label = |name: &str| {
+            let values = result
+                .labels
+                .iter()
+                .filter(|l| l.name == name)
+                .map(|l| l.value.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(values.len(), 1, "{name}: {values:?}");
+            values[0]
+        };
+        assert_eq!(label("language"), "rust");
+        assert_eq!(label("framework"), "allure-rust");
+        assert_eq!(label("package"), "krate.module.sub");
+        assert_eq!(label("testClass"), "krate::module::sub");
+        assert_eq!(label("testMethod"), "test_fn");
+        assert_eq!(label("parentSuite"), "krate");
+        assert_eq!(label("suite"), "module");
+        assert_eq!(label("subSuite"), "custom");
+        assert!(!label("host").is_empty());
+        assert!(label("thread").starts_with(&format!("{}.", std::process::id())));
+        task.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+//! Steps started at runtime from closures, for steps which do not deserve their own function.
+use crate::step_error::any_error_status;
+use crate::{CaughtPanic, TestHelper};
+use allure_models::{Status, StatusDetails};
+use std::fmt::{Debug, Display};
+
+/// What a step reports for the value its closure returned.
+pub trait StepOutcome {
+    fn step_status(&self) -> (Status, Option<StatusDetails>);
+}
+
+impl StepOutcome for () {
+    fn step_status(&self) -> (Status, Option<StatusDetails>) {
+        (Status::Passed, None)
+    }
+}
+
+/// Errors are reported like errors of `allure_step`s, as far as their generic type can be
+/// recognized, see [`crate::step_error`].
+impl<T, E: Display + Debug + 'static> StepOutcome for Result<T, E> {
+    fn step_status(&self) -> (Status, Option<StatusDetails>) {
+        match self {
+            Ok(_) => (Status::Passed, None),
+            Err(err) => {
+                let (status, details) = any_error_status(err);
+                (status, Some(details))
+            }
+        }
+    }
+}
+
+/// A step opened on its own clone of the helper, finalized as `broken` if it is dropped while
+/// still open, e.g. because the future running it was cancelled.
+struct OpenStep {
+    helper: TestHelper,
+    /// The number of steps open on the helper while this step is open.
+    depth: usize,
+}
+
+impl OpenStep {
+    fn start(helper: &TestHelper, name: &str) -> Self {
+        let mut helper = helper.clone();
+        let _ = helper.___private_start_step_sync(name, vec![]);
+        let depth = helper.steps.len();
+        OpenStep { helper, depth }
+    }
+
+    fn finish(mut self, status: Status, status_details: Option<StatusDetails>) {
+        let _ = self
+            .helper
+            .___private_finalize_step_sync(status, status_details);
+    }
+
+    fn finish_panicked(self, panic: &CaughtPanic) {
+        let (status, details) = panic.status();
+        self.finish(status, Some(details))
+    }
+}
+
+impl Drop for OpenStep {
+    fn drop(&mut self) {
+        if self.helper.steps.len() == self.depth {
+            let details = StatusDetails {
+                message: Some("The step was cancelled.".into()),
+                ..Default::default()
+            };
+            let _ = self
+                .helper
+                .___private_finalize_step_sync(Status::Broken, Some(details));
+        }
+    }
+}
+
+impl TestHelper {
+    /// Runs `f` as a step named `name` in the innermost step open on this helper. `f` gets a
+    /// helper for the step, steps started on it are nested in this one. The step's status
+    /// follows the returned [`StepOutcome`], a panic is reported like a panicking test and is re-raised.
+    pub async fn step<F, T>(&self, name: &str, f: F) -> T
+    where
+        F: AsyncFnOnce(&mut TestHelper) -> T,
+        T: StepOutcome,
+    {
+        let mut step = OpenStep::start(self, name);
+        let scope = step.helper.clone();
+        let outcome =
+            crate::___private_catch_unwind(scope.___private_scope(f(&mut step.helper))).await;
+        match outcome {
+            Ok(out) => {
+                let (status, details) = out.step_status();
+                step.finish(status, details);
+                out
+            }
+            Err(panic) => {
+                step.finish_panicked(&panic);
+                panic.resume()
+            }
+        }
+    }
+
+    /// Runs the blocking `f` as a step, like [`TestHelper::step`].
+    pub fn step_sync<F, T>(&self, name: &str, f: F) -> T
+    where
+        F: FnOnce(&mut TestHelper) -> T,
+        T: StepOutcome,
+    {
+        let mut step = OpenStep::start(self, name);
+        let scope = step.helper.clone();
+        let outcome = crate::___private_catch_unwind_sync(|| {
+            scope.___private_sync_scope(|| f(&mut step.helper))
+        });
+        match outcome {
+            Ok(out) => {
+                let (status, details) = out.step_status();
+                step.finish(status, details);
+                out
+            }
+            Err(panic) => {
+                step.finish_panicked(&panic);
+                panic.resume()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::reporter::Mime;
+    use crate::test_support::{start_test, TempDir};
+    use crate::AssertionError;
+    use allure_models::Status;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_closure_steps() {
+        let allure_dir = TempDir::new("closure-steps");
+        let (mut helper, _task) = start_test(Config::new(allure_dir.path()));
+
+        let count = helper
+            .step("outer", async |h| {
+                h.attachment("outer", Mime::Txt, b"").await?;
+                let count = h.step_sync("inner", |_| Ok::<_, AssertionError>(2))?;
+                anyhow::Ok(count)
+            })
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        let err = helper
+            .step("failing", async |_| {
+                Err::<(), _>(anyhow::anyhow!("refused"))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "refused");
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(10),
+            helper.step("cancelled", async |_| {
+                tokio::time::sleep(Duration::from_secs(10)).await
+            }),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        helper.step_sync("passing", |_| ());
+
+        let result = helper.___private_fetch_result().await.unwrap();
+        let steps = result
+            .steps
+            .iter()
+            .map(|s| (s.name.as_str(), s.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            [
+                ("outer", Status::Passed),
+                ("failing", Status::Broken),
+                ("cancelled", Status::Broken),
+                ("passing", Status::Passed)
+            ]
+        );
+        assert_eq!(result.steps[0].attachments[0].name, "outer");
+        assert_eq!(result.steps[0].steps[0].name, "inner");
+        assert_eq!(
+            result.steps[1]
+                .status_details
+                .as_ref()
+                .unwrap()
+                .message
+                .as_deref(),
+            Some("refused")
+        );
+        assert_eq!(
+            result.steps[2]
+                .status_details
+                .as_ref()
+                .unwrap()
+                .message
+                .as_deref(),
+            Some("The step was cancelled.")
+        );
+    }
+}
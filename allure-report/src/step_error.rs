@@ -0,0 +1,266 @@
+//! Picks how the value returned by an `allure_step` is reported, depending on what its type
+//! offers. `(&&&&&Wrap(&res)).step_status()` resolves to the most specific impl: a `Result` whose
+//! error is an `anyhow::Error` or a boxed error, then any `std::error::Error`, then anything
+//! implementing `Display`, then any other error. Other values pass. Closure steps, whose error
+//! type is generic, go through `any_error_status` instead.
+use crate::AssertionError;
+use allure_models::{Status, StatusDetails};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{Debug, Display};
+
+pub struct Wrap<'a, T: ?Sized>(pub &'a T);
+
+pub trait AnyhowErrorStatus {
+    fn step_status(&self) -> Option<(Status, StatusDetails)>;
+}
+
+impl<T> AnyhowErrorStatus for &&&&Wrap<'_, Result<T, anyhow::Error>> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        self.0.as_ref().err().map(crate::error_status)
+    }
+}
+
+/// Boxed errors do not implement `Error`, their sources are reported like those of other errors.
+impl<T> AnyhowErrorStatus for &&&&Wrap<'_, Result<T, Box<dyn Error + Send + Sync>>> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        let err = self.0.as_ref().err()?;
+        Some(source_chain_status(err.as_ref()))
+    }
+}
+
+impl<T> AnyhowErrorStatus for &&&&Wrap<'_, Result<T, Box<dyn Error>>> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        let err = self.0.as_ref().err()?;
+        Some(source_chain_status(err.as_ref()))
+    }
+}
+
+impl<T> AnyhowErrorStatus for &&&&Wrap<'_, Result<T, AssertionError>> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        let err = self.0.as_ref().err()?;
+        Some(source_chain_status(err))
+    }
+}
+
+pub trait StdErrorStatus {
+    fn step_status(&self) -> Option<(Status, StatusDetails)>;
+}
+
+/// Also selected for a generic error type, which may borrow, so only its sources are recognized
+/// as an [`AssertionError`].
+impl<T, E: Error> StdErrorStatus for &&&Wrap<'_, Result<T, E>> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        let err = self.0.as_ref().err()?;
+        Some(sources_status(err))
+    }
+}
+
+/// Reports `err` with its sources, as `failed` if it or one of them is an [`AssertionError`].
+fn source_chain_status(err: &(dyn Error + 'static)) -> (Status, StatusDetails) {
+    let (status, details) = sources_status(err);
+    if err.is::<AssertionError>() {
+        (Status::Failed, details)
+    } else {
+        (status, details)
+    }
+}
+
+/// Reports `err` with its sources, as `failed` if one of its sources is an [`AssertionError`].
+fn sources_status(err: &dyn Error) -> (Status, StatusDetails) {
+    let mut chain = vec![err.to_string()];
+    let mut status = Status::Broken;
+    let mut source = err.source();
+    while let Some(err) = source {
+        if err.is::<AssertionError>() {
+            status = Status::Failed;
+        }
+        chain.push(err.to_string());
+        source = err.source();
+    }
+    let details = StatusDetails {
+        message: Some(chain.join(": ")),
+        trace: Some(format!("{err:?}")),
+        ..Default::default()
+    };
+    (status, details)
+}
+
+pub trait DisplayErrorStatus {
+    fn step_status(&self) -> Option<(Status, StatusDetails)>;
+}
+
+impl<T, E: Display> DisplayErrorStatus for &&Wrap<'_, Result<T, E>> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        let err = self.0.as_ref().err()?;
+        Some((Status::Broken, display_details(err)))
+    }
+}
+
+fn display_details(err: &impl Display) -> StatusDetails {
+    StatusDetails {
+        message: Some(err.to_string()),
+        ..Default::default()
+    }
+}
+
+pub trait OtherErrorStatus {
+    fn step_status(&self) -> Option<(Status, StatusDetails)>;
+}
+
+impl<T, E> OtherErrorStatus for &Wrap<'_, Result<T, E>> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        self.0
+            .as_ref()
+            .err()
+            .map(|_| (Status::Broken, StatusDetails::default()))
+    }
+}
+
+pub trait PassedStatus {
+    fn step_status(&self) -> Option<(Status, StatusDetails)>;
+}
+
+/// Steps which do not return a `Result` pass unless they panic.
+impl<T: ?Sized> PassedStatus for Wrap<'_, T> {
+    fn step_status(&self) -> Option<(Status, StatusDetails)> {
+        None
+    }
+}
+
+/// Like `(&&&&&Wrap(&res)).step_status()` for an error type which is only known to be
+/// `Display`. The impls can not be picked for a generic type, so the error types of this crate,
+/// `anyhow` and boxed errors are recognized at runtime, other errors are reported by their
+/// `Display`.
+pub(crate) fn any_error_status<E: Display + Debug + 'static>(err: &E) -> (Status, StatusDetails) {
+    let any = err as &dyn Any;
+    if let Some(err) = any.downcast_ref::<anyhow::Error>() {
+        return crate::error_status(err);
+    }
+    let source: Option<&(dyn Error + 'static)> =
+        if let Some(err) = any.downcast_ref::<Box<dyn Error + Send + Sync>>() {
+            Some(err.as_ref())
+        } else if let Some(err) = any.downcast_ref::<Box<dyn Error>>() {
+            Some(err.as_ref())
+        } else if let Some(err) = any.downcast_ref::<AssertionError>() {
+            Some(err)
+        } else if let Some(err) = any.downcast_ref::<reqwest_middleware::Error>() {
+            Some(err)
+        } else if let Some(err) = any.downcast_ref::<reqwest::Error>() {
+            Some(err)
+        } else {
+            None
+        };
+    match source {
+        Some(err) => source_chain_status(err),
+        None => {
+            let details = StatusDetails {
+                trace: Some(format!("{err:?}")),
+                ..display_details(err)
+            };
+            (Status::Broken, details)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        any_error_status, AnyhowErrorStatus, DisplayErrorStatus, OtherErrorStatus, PassedStatus,
+        StdErrorStatus, Wrap,
+    };
+    use crate::AssertionError;
+    use allure_models::Status;
+
+    #[derive(Debug, thiserror::Error)]
+    enum DomainError {
+        #[error("comparison failed")]
+        Assertion(#[from] AssertionError),
+    }
+
+    /// The status of a step returning `res`, as in the code generated by `allure_step`.
+    macro_rules! step_status {
+        ($res:expr) => {{
+            #[allow(clippy::needless_borrow)]
+            let status = (&&&&&Wrap(&$res)).step_status();
+            status
+        }};
+    }
+
+    fn assertion() -> AssertionError {
+        AssertionError {
+            description: "equal".into(),
+            diff: "-a\n+b".into(),
+        }
+    }
+
+    #[test]
+    fn test_step_status_dispatch() {
+        let res = Err::<(), _>(anyhow::anyhow!("inner").context("outer"));
+        let (status, details) = step_status!(res).unwrap();
+        assert_eq!(status, Status::Broken);
+        assert_eq!(details.message.as_deref(), Some("outer: inner"));
+
+        let res = Err::<(), _>(DomainError::from(assertion()));
+        let (status, details) = step_status!(res).unwrap();
+        assert_eq!(status, Status::Failed);
+        assert_eq!(
+            details.message.as_deref(),
+            Some("comparison failed: Assertion failed: equal\n-a\n+b")
+        );
+        assert!(details
+            .trace
+            .unwrap()
+            .starts_with("Assertion(AssertionError"));
+
+        let res = Err::<(), _>(assertion());
+        assert_eq!(step_status!(res).unwrap().0, Status::Failed);
+
+        let res = Err::<(), _>("plain message");
+        let (status, details) = step_status!(res).unwrap();
+        assert_eq!(status, Status::Broken);
+        assert_eq!(details.message.as_deref(), Some("plain message"));
+        assert!(details.trace.is_none());
+
+        struct Opaque;
+        let (status, details) = step_status!(Err::<(), _>(Opaque)).unwrap();
+        assert_eq!(status, Status::Broken);
+        assert!(details.message.is_none());
+
+        type Res<T> = Result<T, std::io::Error>;
+        let res: Res<()> = Err(std::io::Error::other("refused"));
+        assert_eq!(step_status!(res).unwrap().0, Status::Broken);
+        assert!(step_status!(Ok::<_, anyhow::Error>(1)).is_none());
+        assert!(step_status!(Some(1)).is_none());
+        assert!(step_status!(()).is_none());
+    }
+
+    #[test]
+    fn test_any_error_status_matches_dispatch() {
+        let err = anyhow::Error::from(assertion()).context("outer");
+        let status = any_error_status(&err);
+        assert_eq!(Some(status), step_status!(Err::<(), _>(err)));
+
+        let boxed = || -> Box<dyn std::error::Error + Send + Sync> {
+            Box::new(DomainError::from(assertion()))
+        };
+        let (status, details) = any_error_status(&boxed());
+        assert_eq!(status, Status::Failed);
+        assert_eq!(
+            details.message.as_deref(),
+            Some("comparison failed: Assertion failed: equal\n-a\n+b")
+        );
+        let res = Err::<(), _>(boxed());
+        assert_eq!(step_status!(res), Some((status, details)));
+        let res = Err::<(), Box<dyn std::error::Error>>(Box::new(assertion()));
+        assert_eq!(step_status!(res).unwrap().0, Status::Failed);
+
+        let err = "plain message";
+        let (status, details) = any_error_status(&err);
+        assert_eq!(status, Status::Broken);
+        assert_eq!(
+            details.message,
+            step_status!(Err::<(), _>(err)).unwrap().1.message
+        );
+    }
+}
@@ -0,0 +1,36 @@
+//! Setup shared by the unit tests.
+
+use crate::config::Config;
+use crate::reporter::Reporter;
+use crate::TestHelper;
+use std::path::{Path, PathBuf};
+use tokio::task::JoinHandle;
+
+/// A directory of the unit test `name` under the temp dir, emptied when created and removed when
+/// dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("allure-report-test-{name}"));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Starts a test reporting with `config`, returning its helper and the spawned reporter task.
+pub(crate) fn start_test(config: Config) -> (TestHelper, JoinHandle<anyhow::Result<()>>) {
+    let (reporter, helper) = Reporter::with_config("name", "full_name", "suite", config);
+    (helper, tokio::spawn(reporter.task()))
+}
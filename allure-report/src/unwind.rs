@@ -0,0 +1,97 @@
+use allure_models::{Status, StatusDetails};
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Once;
+use std::task::Poll;
+
+thread_local! {
+    static LAST_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Keeps the backtrace of the last panic on each thread so it can be added to the report, the
+/// previously installed hook still runs.
+pub(crate) fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            LAST_BACKTRACE.with(|b| *b.borrow_mut() = Some(Backtrace::force_capture()));
+            previous(info)
+        }));
+    });
+}
+
+/// A panic caught by [`___private_catch_unwind`], with the backtrace captured on the thread which
+/// panicked. The test may continue on another worker thread, so the backtrace is not looked up
+/// later.
+pub struct CaughtPanic {
+    payload: Box<dyn Any + Send>,
+    backtrace: Option<Backtrace>,
+}
+
+impl CaughtPanic {
+    /// Called where the panic was caught, which is the thread which panicked.
+    fn caught(payload: Box<dyn Any + Send>) -> Self {
+        CaughtPanic {
+            payload,
+            backtrace: LAST_BACKTRACE.with(|b| b.borrow_mut().take()),
+        }
+    }
+
+    /// The panic's Allure status. Panics of the `assert!` family are reported as `failed`, any
+    /// other panic as `broken`.
+    pub fn status(&self) -> (Status, StatusDetails) {
+        let message = if let Some(message) = self.payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = self.payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "Box<dyn Any>".to_string()
+        };
+        let status = if message.starts_with("assertion") {
+            Status::Failed
+        } else {
+            Status::Broken
+        };
+        let details = StatusDetails {
+            message: Some(message),
+            trace: self.backtrace.as_ref().map(|t| t.to_string()),
+            ..Default::default()
+        };
+        (status, details)
+    }
+
+    /// Continues unwinding. `resume_unwind` does not run the panic hook, so the backtrace is handed
+    /// to the enclosing [`___private_catch_unwind`] through this thread.
+    pub fn resume(self) -> ! {
+        LAST_BACKTRACE.with(|b| *b.borrow_mut() = self.backtrace);
+        std::panic::resume_unwind(self.payload)
+    }
+}
+
+/// Polls `fut` to completion, catching a panic raised while polling it.
+pub async fn ___private_catch_unwind<F: Future>(fut: F) -> Result<F::Output, CaughtPanic> {
+    let mut fut = Box::pin(fut);
+    std::future::poll_fn(
+        move |cx| match catch(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        },
+    )
+    .await
+}
+
+/// Runs `f`, catching a panic raised by it, like [`___private_catch_unwind`].
+pub fn ___private_catch_unwind_sync<R>(f: impl FnOnce() -> R) -> Result<R, CaughtPanic> {
+    catch(AssertUnwindSafe(f))
+}
+
+fn catch<R>(f: AssertUnwindSafe<impl FnOnce() -> R>) -> Result<R, CaughtPanic> {
+    // A backtrace left by a panic which was caught elsewhere does not belong to this one
+    LAST_BACKTRACE.with(|b| b.borrow_mut().take());
+    std::panic::catch_unwind(f).map_err(CaughtPanic::caught)
+}
@@ -18,3 +18,10 @@ pub async fn a_shared_step(
         .await?;
     Ok(res)
 }
+
+#[allure_step(step_description = "Steps can be composed, nested steps show up as a tree.")]
+pub async fn a_composed_step(addr: SocketAddr, test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    a_shared_step(addr, test_helper).await?;
+    a_shared_step(addr, test_helper).await?;
+    Ok(())
+}
@@ -1,2 +1,3 @@
 pub mod common_steps;
+pub mod results;
 pub mod server;
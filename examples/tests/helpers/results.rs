@@ -0,0 +1,15 @@
+use allure_report::config::Config;
+use serde::de::DeserializeOwned;
+
+/// The results in the results directory of this crate's tests, read from the files ending with
+/// `suffix`, such as `-result.json`.
+pub fn read_results<T: DeserializeOwned>(suffix: &str) -> Vec<T> {
+    let config = Config::___private_resolve(None, env!("CARGO_MANIFEST_DIR"));
+    std::fs::read_dir(config.results_dir)
+        .unwrap()
+        .map(|f| f.unwrap().path())
+        .filter(|p| p.to_str().unwrap().ends_with(suffix))
+        // Files of tests running in parallel may be removed meanwhile
+        .filter_map(|p| serde_json::from_slice(&std::fs::read(p).ok()?).ok())
+        .collect()
+}
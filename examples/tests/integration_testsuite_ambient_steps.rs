@@ -0,0 +1,74 @@
+pub mod helpers;
+
+use std::net::SocketAddr;
+
+use allure_report::prelude::*;
+use allure_report::reporter::Mime;
+use allure_report::{allure_step, allure_test, TestHelper};
+
+use crate::helpers::server::Server;
+
+/// A client shared by the test, its steps report to the current test.
+struct Api {
+    addr: SocketAddr,
+    client: reqwest::Client,
+}
+
+impl Api {
+    #[allure_step(step_description = "Fetch the greeting.")]
+    async fn greeting(&self) -> anyhow::Result<String> {
+        let res = self
+            .client
+            .get(format!("http://{}/", self.addr))
+            .send()
+            .await?
+            .text()
+            .await?;
+        allure_report::attach("Greeting", Mime::Txt, res.as_bytes()).await?;
+        Ok(res)
+    }
+}
+
+#[allure_step(step_description = "Check the greeting.", parameters)]
+async fn check_greeting(greeting: &str) {
+    assert_eq!(greeting, "Hello, World!");
+}
+
+#[allure_test(test_description = "Steps report to the current test without a `TestHelper`.")]
+async fn test_ambient_steps() -> anyhow::Result<()> {
+    let server = Server::new(0).await;
+    let api = std::sync::Arc::new(Api {
+        addr: server.addr,
+        client: reqwest::Client::new(),
+    });
+    server.spawn_serve();
+
+    let greeting = api.greeting().await?;
+    check_greeting(&greeting).await;
+
+    // Spawned tasks only report to the test when spawned through `allure_report`
+    let spawned = allure_report::spawn({
+        let api = api.clone();
+        async move { api.greeting().await }
+    });
+    check_greeting(&spawned.await??).await;
+}
+
+#[allure_test(test_description = "Steps with and without a `TestHelper` can be mixed.")]
+async fn test_mixed_steps(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    test_helper.label("layer", "unit")?;
+    check_greeting("Hello, World!").await;
+    allure_report::attach("Note", Mime::Txt, b"attached to the test").await?;
+}
+
+#[allure_step(step_description = "Count the words.")]
+fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+// Outside of an allure test, steps without a `TestHelper` run without being reported
+#[tokio::test]
+async fn test_steps_outside_of_a_test() {
+    check_greeting("Hello, World!").await;
+    assert_eq!(count_words("Hello, World!"), 2);
+}
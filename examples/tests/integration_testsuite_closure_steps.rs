@@ -0,0 +1,37 @@
+pub mod helpers;
+
+use allure_report::prelude::*;
+use allure_report::reporter::Mime;
+use allure_report::{allure_test, TestHelper};
+
+use crate::helpers::server::Server;
+
+#[allure_test(test_description = "Steps can be started from closures.")]
+async fn test_closure_steps(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let server = Server::new(0).await;
+    let addr = server.addr;
+    server.spawn_serve();
+
+    let greeting = test_helper
+        .step("Request the greeting", async |h| {
+            let res = h
+                .client()
+                .get(format!("http://{}/", addr))
+                .send()
+                .await?
+                .text()
+                .await?;
+            h.step("Check the greeting", async |h| {
+                h.attachment("Greeting", Mime::Txt, res.as_bytes()).await?;
+                anyhow::ensure!(res == "Hello, World!", "unexpected greeting {res}");
+                Ok(())
+            })
+            .await?;
+            anyhow::Ok(res)
+        })
+        .await?;
+
+    test_helper.step_sync("Count the words", |_| {
+        assert_eq!(greeting.split(' ').count(), 2);
+    });
+}
@@ -0,0 +1,56 @@
+pub mod helpers;
+
+use std::net::SocketAddr;
+
+use allure_report::prelude::*;
+use allure_report::{allure_step, allure_test, TestHelper};
+
+use crate::helpers::server::Server;
+
+#[allure_step(step_description = "Request the greeting.", parameters)]
+async fn greet(addr: SocketAddr, attempt: u8, test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let res = test_helper
+        .client()
+        .get(format!("http://{}/", addr))
+        .send()
+        .await?
+        .text()
+        .await?;
+    anyhow::ensure!(res == "Hello, World!", "attempt {attempt} got {res}");
+    Ok(())
+}
+
+#[allure_step(
+    step_description = "Request the greeting from several tasks.",
+    parameters
+)]
+async fn greet_from_tasks(addr: SocketAddr, tasks: u8) -> anyhow::Result<()> {
+    let handles = (0..tasks)
+        .map(|attempt| {
+            allure_report::spawn(async move {
+                let mut test_helper = TestHelper::current().unwrap();
+                greet(addr, attempt, &mut test_helper).await
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await??;
+    }
+    Ok(())
+}
+
+#[allure_test(test_description = "Steps running concurrently get their own entries.")]
+async fn test_concurrent_steps(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let server = Server::new(0).await;
+    let addr = server.addr;
+    server.spawn_serve();
+
+    // Each concurrently running step reports through its own clone of the helper
+    let (mut first, mut second) = (test_helper.clone(), test_helper.clone());
+    let (a, b) = tokio::join!(greet(addr, 1, &mut first), greet(addr, 2, &mut second));
+    a?;
+    b?;
+
+    // Steps in spawned tasks are nested in the step which spawned them
+    greet_from_tasks(addr, 3).await?;
+}
@@ -0,0 +1,192 @@
+pub mod helpers;
+
+use allure_report::prelude::anyhow::Context;
+use allure_report::prelude::*;
+use allure_report::{allure_step, allure_test};
+
+use allure_report::models::{Status, TestResult};
+use allure_report::reporter::{Mime, Reporter};
+use allure_report::{___private_catch_unwind, AssertionError, TestHelper};
+
+#[derive(Debug, thiserror::Error)]
+enum UserError {
+    #[error("user {0} not found")]
+    NotFound(String),
+}
+
+#[allure_test(test_description = "Errors returned by steps keep their type and source chain.")]
+async fn test_step_errors_are_propagated(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let err = look_up_user("alice", test_helper).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<UserError>(),
+        Some(UserError::NotFound(name)) if name == "alice"
+    ));
+    assert_eq!(format!("{err:#}"), "looking up alice: user alice not found");
+}
+
+#[allure_step(step_description = "This step fails with a domain error.")]
+async fn look_up_user(name: &str, test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    test_helper
+        .attachment("User name", Mime::Txt, name.as_bytes())
+        .await?;
+    Err(UserError::NotFound(name.to_string())).with_context(|| format!("looking up {name}"))
+}
+
+#[allure_test(test_description = "Steps may return any error type, or no Result at all.")]
+async fn test_step_return_types(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let err = delete_user("bob", test_helper).await.unwrap_err();
+    assert!(matches!(err, UserError::NotFound(name) if name == "bob"));
+
+    let err = call_closed_port(test_helper).await.unwrap_err();
+    assert!(matches!(err, reqwest_middleware::Error::Reqwest(_)));
+
+    assert_eq!(count_users(test_helper).await, 0);
+    log_users(test_helper).await;
+}
+
+#[allure_step(step_description = "This step fails with an error which is not anyhow's.")]
+async fn delete_user(name: &str, test_helper: &mut TestHelper) -> Result<(), UserError> {
+    test_helper
+        .attachment("User name", Mime::Txt, name.as_bytes())
+        .await
+        .unwrap();
+    Err(UserError::NotFound(name.to_string()))
+}
+
+#[allure_step(step_description = "Requests to a closed port fail in the middleware.")]
+async fn call_closed_port(
+    test_helper: &mut TestHelper,
+) -> reqwest_middleware::Result<reqwest::Response> {
+    test_helper.client().get("http://127.0.0.1:1/").send().await
+}
+
+#[allure_step(step_description = "Steps without a Result pass unless they panic.")]
+async fn count_users(test_helper: &mut TestHelper) -> usize {
+    test_helper
+        .attachment("Users", Mime::Txt, b"")
+        .await
+        .unwrap();
+    0
+}
+
+#[allure_step(step_description = "Steps may return nothing at all.")]
+async fn log_users(test_helper: &mut TestHelper) {
+    test_helper
+        .attachment("Users", Mime::Txt, b"")
+        .await
+        .unwrap();
+}
+
+type IoResult<T> = Result<T, std::io::Error>;
+
+#[allure_step(step_description = "Steps may return an alias of Result.")]
+async fn read_settings(test_helper: &mut TestHelper) -> IoResult<String> {
+    Err(std::io::Error::other("settings not found"))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("users differ")]
+struct UsersDiffer(#[source] AssertionError);
+
+#[allure_step(step_description = "Boxed errors are reported with their sources.")]
+async fn compare_users(
+    test_helper: &mut TestHelper,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    Err(Box::new(UsersDiffer(AssertionError {
+        description: "the user count".into(),
+        diff: "-1\n+2".into(),
+    })))
+}
+
+#[allure_step(step_description = "Steps may be generic over their error.")]
+async fn fail_with<E: std::error::Error>(err: E, test_helper: &mut TestHelper) -> Result<(), E> {
+    Err(err)
+}
+
+// Not an `allure_test`, which would fail with the steps: reads back their statuses instead
+#[tokio::test]
+async fn test_step_error_statuses() {
+    let allure_dir = std::env::temp_dir().join("allure-examples-step-error-statuses");
+    let (reporter, mut helper) = Reporter::new(
+        "test_step_error_statuses",
+        "test_step_error_statuses",
+        module_path!(),
+        allure_dir.to_str().unwrap(),
+    );
+    tokio::spawn(reporter.task());
+
+    assert!(read_settings(&mut helper).await.is_err());
+    assert!(compare_users(&mut helper).await.is_err());
+    let err = UserError::NotFound("carol".into());
+    assert!(fail_with(err, &mut helper).await.is_err());
+
+    let result = helper.___private_fetch_result().await.unwrap();
+    let steps = result
+        .steps
+        .iter()
+        .map(|s| {
+            let message = s.status_details.as_ref().and_then(|d| d.message.as_deref());
+            (s.status, message)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        [
+            (Status::Broken, Some("settings not found")),
+            (
+                Status::Failed,
+                Some("users differ: Assertion failed: the user count\n-1\n+2")
+            ),
+            (Status::Broken, Some("user carol not found")),
+        ]
+    );
+    let _ = std::fs::remove_dir_all(&allure_dir);
+}
+
+#[allure_step(step_description = "This step panics.")]
+async fn parse_port(input: &str, test_helper: &mut TestHelper) -> u16 {
+    test_helper
+        .attachment("Input", Mime::Txt, input.as_bytes())
+        .await
+        .unwrap();
+    input.parse().unwrap()
+}
+
+// Not an `allure_test`, which would fail with the step: reads back the reported result instead
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_step_panic_is_reported() {
+    let allure_dir = std::env::temp_dir().join("allure-examples-step-panic");
+    let _ = std::fs::remove_dir_all(&allure_dir);
+    let (reporter, mut helper) = Reporter::new(
+        "test_step_panic_is_reported",
+        "test_step_panic_is_reported",
+        module_path!(),
+        allure_dir.to_str().unwrap(),
+    );
+    tokio::spawn(reporter.task());
+
+    let mut step_helper = helper.clone();
+    let outcome = ___private_catch_unwind(async {
+        parse_port("http", &mut step_helper).await;
+        anyhow::Ok(())
+    })
+    .await;
+    let finished = ___private_catch_unwind(helper.___private_finish(outcome)).await;
+    assert!(finished.is_err(), "the step's panic is re-raised");
+
+    let file = std::fs::read_dir(&allure_dir)
+        .unwrap()
+        .map(|f| f.unwrap().path())
+        .find(|p| p.to_str().unwrap().ends_with("-result.json"))
+        .unwrap();
+    let result: TestResult = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+    let step = &result.steps[0];
+    assert_eq!(step.name, "parse_port: This step panics.");
+    assert_eq!(step.status, Status::Broken);
+    assert_eq!(result.status, step.status);
+    let step_details = step.status_details.as_ref().unwrap();
+    let test_details = result.status_details.as_ref().unwrap();
+    assert_eq!(step_details.message, test_details.message);
+    assert!(step_details.trace.as_ref().unwrap().contains("parse_port"));
+    assert_eq!(step_details.trace, test_details.trace);
+}
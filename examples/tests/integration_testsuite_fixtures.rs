@@ -0,0 +1,83 @@
+pub mod helpers;
+
+use std::net::SocketAddr;
+
+use allure_report::prelude::*;
+use allure_report::{allure_after, allure_before, allure_test};
+
+use crate::helpers::common_steps::a_shared_step;
+use crate::helpers::results::read_results;
+use crate::helpers::server::Server;
+use allure_report::models::{Status, TestResult, TestResultContainer};
+use allure_report::TestHelper;
+
+#[allure_test(test_description = "Setup and teardown are reported as fixtures.")]
+async fn test_feature_with_fixtures(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let addr = start_server(test_helper).await?;
+
+    a_shared_step(addr, test_helper).await?;
+
+    check_server_still_up(addr, test_helper).await?;
+}
+
+#[allure_before(step_description = "Start the server under test.")]
+async fn start_server(test_helper: &mut TestHelper) -> anyhow::Result<SocketAddr> {
+    let server = Server::new(0).await;
+    let addr = server.addr;
+    server.spawn_serve();
+    // Steps and attachments inside a fixture belong to the fixture
+    a_shared_step(addr, test_helper).await?;
+    Ok(addr)
+}
+
+#[allure_after(
+    step_description = "Make sure the server survived the test.",
+    parameters
+)]
+async fn check_server_still_up(
+    addr: SocketAddr,
+    test_helper: &mut TestHelper,
+) -> anyhow::Result<()> {
+    a_shared_step(addr, test_helper).await?;
+    Ok(())
+}
+
+// Runs the test above again to read back its container
+#[test]
+fn test_fixtures_are_reported_in_a_container() {
+    test_feature_with_fixtures();
+    let full_name = format!("{}::test_feature_with_fixtures", module_path!());
+    let result = read_results::<TestResult>("-result.json")
+        .into_iter()
+        .filter(|result| result.full_name == full_name)
+        .max_by_key(|result| result.start)
+        .unwrap();
+    let containers = read_results::<TestResultContainer>("-container.json")
+        .into_iter()
+        .filter(|container| container.children.contains(&result.uuid))
+        .collect::<Vec<_>>();
+    assert_eq!(containers.len(), 1);
+    let container = &containers[0];
+    assert_eq!(container.children, [result.uuid]);
+    let fixtures = container
+        .befores
+        .iter()
+        .chain(&container.afters)
+        .map(|f| (f.name.as_str(), f.status))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fixtures,
+        [
+            ("start_server: Start the server under test.", Status::Passed),
+            (
+                "check_server_still_up: Make sure the server survived the test.",
+                Status::Passed
+            )
+        ]
+    );
+    assert_eq!(
+        container.befores[0].steps.len(),
+        1,
+        "the fixture's own step"
+    );
+}
@@ -0,0 +1,96 @@
+pub mod helpers;
+
+use std::fmt::Debug;
+use std::net::SocketAddr;
+
+use allure_report::prelude::reqwest::Method;
+use allure_report::prelude::*;
+use allure_report::reporter::Mime;
+use allure_report::{allure_step, allure_test, TestHelper};
+use serde::Serialize;
+
+use crate::helpers::server::{Server, Test};
+
+#[allure_test(test_description = "Steps may be generic and destructure their arguments.")]
+async fn test_generic_steps(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let server = Server::new(0).await;
+    let addr = server.addr;
+    server.spawn_serve();
+
+    let body = Test {
+        a: "XYZ".to_string(),
+    };
+    let created = create(addr, &body, test_helper).await?;
+    assert_eq!(created, serde_json::json!({"a": "b"}));
+
+    assert_eq!(first_word("hello allure", test_helper).await, "hello");
+    assert_eq!(describe(Some(1), test_helper).await, "Some(1)");
+    assert_eq!(add((1, 2), 3, test_helper).await, 3);
+    assert_eq!(default_value::<u8>(test_helper).await, 0);
+    assert_eq!(count([1, 2, 3], test_helper).await, 3);
+}
+
+#[allure_step(step_description = "Generic over the request body.")]
+async fn create<T: Serialize>(
+    addr: SocketAddr,
+    body: &T,
+    test_helper: &mut TestHelper,
+) -> anyhow::Result<serde_json::Value> {
+    let client = test_helper.client();
+    let builder = client
+        .request(Method::POST, format!("http://{}/json", addr))
+        .json(body);
+    Ok(client.execute(builder.build()?).await?.json().await?)
+}
+
+#[allure_step(step_description = "Borrows its result from an argument.")]
+async fn first_word<'a>(text: &'a str, test_helper: &mut TestHelper) -> &'a str {
+    test_helper
+        .attachment("Text", Mime::Txt, text.as_bytes())
+        .await
+        .unwrap();
+    text.split(' ').next().unwrap()
+}
+
+#[allure_step(step_description = "Bounds in a where clause.", parameters)]
+async fn describe<T>(value: T, test_helper: &mut TestHelper) -> String
+where
+    T: Debug,
+{
+    let description = format!("{value:?}");
+    test_helper
+        .attachment("Description", Mime::Txt, description.as_bytes())
+        .await
+        .unwrap();
+    description
+}
+
+#[allure_step(step_description = "Destructures and ignores arguments.")]
+async fn add((a, b): (u8, u8), _: u8, test_helper: &mut TestHelper) -> u8 {
+    let sum = a + b;
+    test_helper
+        .attachment("Sum", Mime::Txt, sum.to_string().as_bytes())
+        .await
+        .unwrap();
+    sum
+}
+
+#[allure_step(step_description = "Generic only over its return type.")]
+async fn default_value<T: Default + Debug>(test_helper: &mut TestHelper) -> T {
+    let value = T::default();
+    test_helper
+        .attachment("Value", Mime::Txt, format!("{value:?}").as_bytes())
+        .await
+        .unwrap();
+    value
+}
+
+#[allure_step(step_description = "Takes an impl Trait argument.")]
+async fn count(items: impl IntoIterator<Item = u8>, test_helper: &mut TestHelper) -> usize {
+    let count = items.into_iter().count();
+    test_helper
+        .attachment("Count", Mime::Txt, count.to_string().as_bytes())
+        .await
+        .unwrap();
+    count
+}
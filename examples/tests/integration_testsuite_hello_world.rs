@@ -11,7 +11,15 @@ use allure_report::TestHelper;
 
 // Unnamed tests take the function name as the test name
 #[allure_test(
-    test_description = "This test makes sure that our server hello worlds in proper manner."
+    test_description = "This test makes sure that our server hello worlds in proper manner.",
+    epic = "Web interface",
+    feature = "Greeting",
+    story = "Hello World",
+    severity = "critical",
+    owner = "core-team",
+    tag = "smoke",
+    tag = "http",
+    issue = "BUG-123"
 )]
 async fn test_feature_hello_world(test_helper: &mut TestHelper) -> anyhow::Result<()> {
     let server = Server::new(0).await;
@@ -21,7 +29,11 @@ async fn test_feature_hello_world(test_helper: &mut TestHelper) -> anyhow::Resul
     make_sure_hello_world_works(addr, test_helper).await?;
 }
 
-#[allure_step(step_description = "Test the server responds 'Hello World!'.")]
+// Arguments can be recorded as step parameters, the test helper is never recorded
+#[allure_step(
+    step_description = "Test the server responds 'Hello World!'.",
+    parameters
+)]
 pub async fn make_sure_hello_world_works(
     addr: SocketAddr,
     test_helper: &mut TestHelper,
@@ -40,3 +52,33 @@ pub async fn make_sure_hello_world_works(
         .await?;
     Ok(res)
 }
+
+// Tests which do not report steps need no test helper
+#[allure_test(
+    test_description = "The server binds to a free port.",
+    sub_suite = "Server"
+)]
+async fn test_server_binds() -> anyhow::Result<()> {
+    let server = Server::new(0).await;
+    assert_ne!(server.addr.port(), 0);
+}
+
+#[allure_test(test_description = "The test helper may have any name.")]
+async fn test_helper_name(helper: &mut TestHelper) -> anyhow::Result<()> {
+    let server = Server::new(0).await;
+    let addr = server.addr;
+    server.spawn_serve();
+
+    assert_eq!(greeting(addr, helper).await?, "Hello, World!");
+}
+
+#[allure_step(step_description = "Fetch the greeting.")]
+async fn greeting(addr: SocketAddr, h: &mut TestHelper) -> anyhow::Result<String> {
+    let client = h.client();
+    Ok(client
+        .get(format!("http://{}/", addr))
+        .send()
+        .await?
+        .text()
+        .await?)
+}
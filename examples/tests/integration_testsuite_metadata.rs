@@ -0,0 +1,54 @@
+pub mod helpers;
+
+use allure_report::allure_test;
+use allure_report::models::{Label, TestResult};
+use allure_report::prelude::*;
+use allure_report::TestHelper;
+
+use crate::helpers::results::read_results;
+
+/// Reports its *metadata*.
+#[allure_test(epic = "Reporting", feature = "Metadata", tag = "e2e")]
+async fn test_described_by_its_doc_comment() {}
+
+/// Static doc description.
+#[allure_test(feature = "Metadata")]
+async fn test_described_at_runtime(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    test_helper.description("Runtime description.")?;
+}
+
+/// The latest result of the test `name` of this file.
+fn read_result(name: &str) -> TestResult {
+    let full_name = format!("{}::{name}", module_path!());
+    read_results::<TestResult>("-result.json")
+        .into_iter()
+        .filter(|result| result.full_name == full_name)
+        .max_by_key(|result| result.start)
+        .unwrap()
+}
+
+// Runs the tests above again to read back their results
+#[test]
+fn test_metadata_is_reported() {
+    test_described_by_its_doc_comment();
+    let result = read_result("test_described_by_its_doc_comment");
+    assert_eq!(result.name, "test_described_by_its_doc_comment");
+    assert_eq!(
+        result.description.as_deref(),
+        Some("Reports its *metadata*.")
+    );
+    assert_eq!(result.description_html, None);
+    for label in [
+        Label::new("epic", "Reporting"),
+        Label::new("feature", "Metadata"),
+        Label::new("tag", "e2e"),
+        Label::new("testMethod", "test_described_by_its_doc_comment"),
+    ] {
+        assert!(result.labels.contains(&label), "{label:?}");
+    }
+
+    test_described_at_runtime();
+    let result = read_result("test_described_at_runtime");
+    assert_eq!(result.description.as_deref(), Some("Runtime description."));
+    assert_eq!(result.description_html, None);
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use allure_report::prelude::*;
+use allure_report::{allure_step, allure_test, TestHelper};
+
+#[allure_step(step_description = "Wait for the cache to expire.", parameters)]
+async fn wait_for_expiry(seconds: u64) {
+    tokio::time::sleep(Duration::from_secs(seconds)).await;
+}
+
+#[allure_test(
+    test_description = "Time dependent tests run on a paused clock.",
+    start_paused = true
+)]
+async fn test_paused_clock() -> anyhow::Result<()> {
+    let start = tokio::time::Instant::now();
+    wait_for_expiry(3600).await;
+    assert!(start.elapsed() >= Duration::from_secs(3600));
+}
+
+#[allure_test(
+    test_description = "Steps may run on a multi threaded runtime.",
+    flavor = "multi_thread",
+    worker_threads = 2
+)]
+async fn test_multi_thread(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let flavor = tokio::runtime::Handle::current().runtime_flavor();
+    assert_eq!(flavor, tokio::runtime::RuntimeFlavor::MultiThread);
+    let spawned = allure_report::spawn(async { wait_for_expiry(0).await });
+    spawned.await?;
+    test_helper.label("runtime", "multi_thread")?;
+}
+
+#[allure_test(test_description = "An existing tokio::test attribute is kept.")]
+#[tokio::test(start_paused = true)]
+async fn test_composes_with_tokio_test() -> anyhow::Result<()> {
+    wait_for_expiry(60).await;
+}
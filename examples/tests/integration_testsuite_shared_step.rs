@@ -3,7 +3,7 @@ pub mod helpers;
 use allure_report::allure_test;
 use allure_report::prelude::*;
 
-use crate::helpers::common_steps::a_shared_step;
+use crate::helpers::common_steps::{a_composed_step, a_shared_step};
 use crate::helpers::server::Server;
 use allure_report::TestHelper;
 use tracing::level_filters::LevelFilter;
@@ -37,6 +37,21 @@ async fn test_feature_b(test_helper: &mut TestHelper) -> anyhow::Result<()> {
     let addr = server.addr;
 
     server.spawn_serve();
+    // Metadata only known at runtime can be added while the test runs
+    test_helper.link("Server under test", &format!("http://{addr}/"))?;
 
     a_shared_step(addr, test_helper).await?;
 }
+
+#[allure_test(
+    test_name = "composed steps",
+    test_description = "A step calling other steps"
+)]
+async fn test_feature_composed(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let server = Server::new(0).await;
+    let addr = server.addr;
+
+    server.spawn_serve();
+
+    a_composed_step(addr, test_helper).await?;
+}
@@ -71,7 +71,10 @@ impl Tester {
     }
 }
 
-#[allure_test(test_name = "hello2", test_description = "this-that")]
+/// Without `test_description` the doc comment becomes the description.
+///
+/// It is rendered as **Markdown**.
+#[allure_test(test_name = "hello2")]
 async fn test_feature_b(test_helper: &mut TestHelper) -> anyhow::Result<()> {
     let server = Server::new(0).await;
     let addr = server.addr;
@@ -0,0 +1,56 @@
+use allure_report::prelude::*;
+use allure_report::reporter::Mime;
+use allure_report::{allure_before, allure_step, allure_test, TestHelper};
+
+#[allure_before(step_description = "Prepare the input.")]
+fn prepare(test_helper: &mut TestHelper) -> Vec<u32> {
+    test_helper
+        .attachment_sync("Input", Mime::Txt, b"1 2 3")
+        .unwrap();
+    vec![1, 2, 3]
+}
+
+#[allure_step(step_description = "Sum the numbers.", parameters)]
+fn sum(numbers: &[u32], test_helper: &mut TestHelper) -> u32 {
+    let sum = numbers.iter().sum();
+    test_helper
+        .attachment_sync("Sum", Mime::Txt, format!("{sum}").as_bytes())
+        .unwrap();
+    sum
+}
+
+#[allure_step(step_description = "Parse a number.", parameters)]
+fn parse(input: &str) -> anyhow::Result<u32> {
+    Ok(input.parse()?)
+}
+
+/// A secret, which can not be formatted.
+struct Password(String);
+
+#[allure_step(step_description = "Log in.", parameters, mask(password))]
+fn log_in(user: &str, password: Password) -> bool {
+    user == "alice" && password.0 == "hunter2"
+}
+
+#[allure_test(test_description = "Synchronous tests and steps need no runtime.")]
+fn test_sync_steps(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    assert!(tokio::runtime::Handle::try_current().is_err());
+    let numbers = prepare(test_helper);
+    assert_eq!(sum(&numbers, test_helper), 6);
+    assert_eq!(parse("42")?, 42);
+    assert!(parse("forty-two").is_err());
+    assert!(log_in("alice", Password("hunter2".into())));
+    test_helper.label("layer", "unit")?;
+    test_helper
+        .asserter()
+        .assert_that(6u32)
+        .is_equals_to_sync(numbers.iter().sum::<u32>(), Some("the sum of the input"))?;
+    test_helper.step_sync("Sum again", |h| {
+        assert_eq!(sum(&numbers, h), 6);
+    });
+}
+
+#[allure_test]
+fn test_sync_without_helper() -> anyhow::Result<()> {
+    assert_eq!(parse("7")?, 7);
+}
@@ -0,0 +1,71 @@
+pub mod helpers;
+
+use allure_report::prelude::*;
+use allure_report::reporter::Mime;
+use allure_report::{allure_step, allure_test, TestHelper};
+
+trait Shop {
+    fn items(&self) -> Vec<String>;
+
+    async fn buy(&self, item: &str, test_helper: &mut TestHelper) -> anyhow::Result<()>;
+
+    #[allure_step(step_description = "A step in a trait's default method.")]
+    async fn list(&self, test_helper: &mut TestHelper) -> usize {
+        let items = self.items().join("\n");
+        test_helper
+            .attachment("Items", Mime::Txt, items.as_bytes())
+            .await
+            .unwrap();
+        self.items().len()
+    }
+}
+
+#[async_trait::async_trait]
+trait Warehouse {
+    async fn restock(&mut self, item: &str, test_helper: &mut TestHelper) -> anyhow::Result<()>;
+}
+
+struct Store {
+    items: Vec<String>,
+}
+
+impl Shop for Store {
+    fn items(&self) -> Vec<String> {
+        self.items.clone()
+    }
+
+    #[allure_step(step_description = "A step in a trait impl.", parameters)]
+    async fn buy(&self, item: &str, test_helper: &mut TestHelper) -> anyhow::Result<()> {
+        test_helper
+            .attachment("Item", Mime::Txt, item.as_bytes())
+            .await?;
+        if !self.items.iter().any(|i| i == item) {
+            anyhow::bail!("{item} is sold out");
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Warehouse for Store {
+    #[allure_step(step_description = "A step in an async_trait impl.", parameters)]
+    async fn restock(&mut self, item: &str, test_helper: &mut TestHelper) -> anyhow::Result<()> {
+        test_helper
+            .attachment("Item", Mime::Txt, item.as_bytes())
+            .await?;
+        self.items.push(item.to_string());
+        Ok(())
+    }
+}
+
+#[allure_test(test_description = "Steps may be trait methods.")]
+async fn test_trait_steps(test_helper: &mut TestHelper) -> anyhow::Result<()> {
+    let mut store = Store {
+        items: vec!["apple".to_string()],
+    };
+    assert_eq!(store.list(test_helper).await, 1);
+    store.buy("apple", test_helper).await?;
+    assert!(store.buy("pear", test_helper).await.is_err());
+    store.restock("pear", test_helper).await?;
+    store.buy("pear", test_helper).await?;
+}